@@ -59,6 +59,9 @@ pub struct ExtendedLaunchArguments {
     pub inner: LaunchRequestArguments,
     /// debuggee
     pub program: String,
+    /// stop at the first op of the debuggee instead of running it
+    #[serde(default)]
+    pub stop_on_entry: bool,
 }
 
 /// Mirror of the ProtocolMessage debug_types enum, with some modifications for custom args
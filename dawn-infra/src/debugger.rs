@@ -17,7 +17,7 @@ use std::{
     thread::JoinHandle,
 };
 use tokio_util::codec::{FramedRead, FramedWrite};
-use tvix_debugger::commands::{Command, CommandReply};
+use tvix_debugger::commands::{Command, CommandReply, ObserverEvent};
 
 use either::Either;
 use tracing::error;
@@ -52,6 +52,8 @@ pub struct Server {
     pub sender: mpsc::Sender<Command>,
     /// channel for replies from the debugger
     pub receiver: mpsc::Receiver<CommandReply>,
+    /// channel for events the debugger reports on its own
+    pub events: mpsc::Receiver<ObserverEvent>,
     /// debugger handle
     pub debugger: JoinHandle<()>,
     /// shutdown token
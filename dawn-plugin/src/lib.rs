@@ -11,10 +11,11 @@
 
 use dawn_infra::codec::DebugAdapterCodec;
 use dawn_infra::dap_requests::ExtendedProtocolMessage;
-use dawn_infra::debugger::{Client, DebugAdapter, State};
+use dawn_infra::debugger::{Client, DebugAdapter, Server, State};
 use nix_debugger::{NixDebugAdapter, NixDebugState};
 use tokio_util::codec::{FramedRead, FramedWrite};
-use tracing::error;
+use tracing::{debug, error};
+use tvix_debugger::commands::ObserverEvent;
 
 /// debugger
 pub mod nix_debugger;
@@ -33,28 +34,39 @@ where
         writer,
         DebugAdapterCodec::<ExtendedProtocolMessage>::default(),
     );
-    println!("Framed reader and writer initialized");
-
     let client = Client::new(reader, writer);
     let mut adapter = NixDebugAdapter {
         client,
         state: NixDebugState::default(),
         server: None,
     };
-    println!("Adapter initialized, entering message loop");
+    debug!("adapter initialized, entering message loop");
 
     while adapter.client.get_state() < State::ShutDown {
         use dawn_infra::dap_requests::ExtendedMessageKind::{Event, Request, Response};
-        let msg = adapter.client.next_msg().await;
-        println!("got a message {msg:?}");
-        match msg.message {
-            Request(request) => adapter.handle_request(msg.seq, request).await,
-            Response(response) => {
-                error!("Received response {response:?}. Shouldn't be possible!");
+        tokio::select! {
+            msg = adapter.client.next_msg() => {
+                debug!("got a message {msg:?}");
+                match msg.message {
+                    Request(request) => adapter.handle_request(msg.seq, request).await,
+                    Response(response) => {
+                        error!("Received response {response:?}. Shouldn't be possible!");
+                    }
+                    Event(e) => error!("Received event {e:?}. Shouldn't be possible!"),
+                }
             }
-            Event(e) => error!("Received event {e:?}. Shouldn't be possible!"),
+            Some(event) = next_event(adapter.server.as_mut()) => adapter.handle_event(event).await,
         }
     }
 
-    println!("Adapter exited");
+    debug!("adapter exited");
+}
+
+/// Wait for the next event of the debugger, never resolves before the
+/// debugger is initialized
+async fn next_event(server: Option<&mut Server>) -> Option<ObserverEvent> {
+    match server {
+        Some(server) => server.events.recv().await,
+        None => std::future::pending().await,
+    }
 }
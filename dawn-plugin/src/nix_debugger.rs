@@ -1,10 +1,31 @@
-use std::sync::{atomic::AtomicBool, Arc};
+use std::{
+    path::Path,
+    sync::{atomic::AtomicBool, Arc},
+};
 
 use debug_types::{
-    events::EventBody,
-    requests::{BreakpointLocationsArguments, InitializeRequestArguments},
-    responses::{BreakpointLocationsResponse, InitializeResponse, Response, ResponseBody},
-    types::BreakpointLocation,
+    events::{BreakpointEventReason, EventBody, StoppedEventReason},
+    requests::{
+        BreakpointLocationsArguments, CompletionsArguments, EvaluateArguments,
+        ExceptionInfoArguments, InitializeRequestArguments, ScopesArguments,
+        SetBreakpointsArguments, SetExceptionBreakpointsArguments, SetExpressionArguments,
+        SetFunctionBreakpointsArguments, SetVariableArguments, StackTraceArguments,
+        VariablesArguments,
+    },
+    responses::{
+        BreakpointLocationsResponse, CompletionsResponse, ContinueResponse, EvaluateResponse,
+        ExceptionInfoResponse, InitializeResponse, Response, ResponseBody, ScopesResponse,
+        SetBreakpointsResponse, SetExceptionBreakpointsResponse, SetExpressionResponse,
+        SetFunctionBreakpointsResponse, SetVariableResponse, StackTraceResponse, ThreadsResponse,
+        VariablesResponse,
+    },
+    types::{
+        Breakpoint as DapBreakpoint, CompletionItem, CompletionItemType, ExceptionBreakMode,
+        ExceptionDetails, Scope as DapScope, ScopePresentationhint, Source,
+        StackFrame as DapStackFrame, StackFramePresentationhint,
+        SteppingGranularity as DapSteppingGranularity, Thread, Variable as DapVariable,
+        VariablePresentationHint,
+    },
 };
 use either::Either;
 
@@ -13,15 +34,195 @@ use dawn_infra::{
     dap_requests::ExtendedRequestCommand,
     debugger::{Client, DebugAdapter, Server, State},
 };
-use nll::nll_todo::nll_todo;
 use tokio::io::{AsyncRead, AsyncWrite};
 use tracing::error;
 use tvix_debugger::{
-    backend::DebuggerState,
-    commands::{default_capabilities, Command, CommandReply},
+    backend::{self, DebuggerState},
+    breakpoints::BreakpointEntry,
+    commands::{
+        default_capabilities, Breakpoint, Command, CommandReply, EvaluateContext, ObserverEvent,
+        SteppingGranularity, StopReason,
+    },
+    complete::{Completion, CompletionKind},
     config::Args,
+    exceptions,
+    stack::{FrameKind, Scope, ScopeKind, StackFrame},
+    variables::Variable,
 };
 
+/// tvix evaluates on a single thread, this is its id
+const THREAD_ID: i64 = 1;
+
+/// a scope of the debugger as DAP shows it, builtins are many and expensive
+fn dap_scope(scope: Scope) -> DapScope {
+    DapScope {
+        name: scope.name,
+        presentation_hint: (scope.kind == ScopeKind::Locals)
+            .then_some(ScopePresentationhint::Locals),
+        variables_reference: scope.variables_reference as i64,
+        named_variables: Some(scope.named_variables as i64),
+        indexed_variables: None,
+        expensive: scope.kind == ScopeKind::Builtins,
+        source: None,
+        line: None,
+        column: None,
+        end_line: None,
+        end_column: None,
+    }
+}
+
+/// a variable of the debugger as DAP shows it, overridden values are marked
+/// in their type, clients compare and copy the value itself
+fn dap_variable(variable: Variable) -> DapVariable {
+    let presentation_hint = variable.lazy.then_some(VariablePresentationHint {
+        kind: None,
+        attributes: None,
+        visibility: None,
+        lazy: Some(true),
+    });
+    let type_field = match variable.type_name {
+        Some(type_name) if variable.overridden => Some(format!("{type_name} (overridden)")),
+        type_name => type_name,
+    };
+    DapVariable {
+        name: variable.name,
+        value: variable.value,
+        type_field,
+        presentation_hint,
+        evaluate_name: None,
+        variables_reference: variable.variables_reference as i64,
+        named_variables: variable.named_variables.map(|n| n as i64),
+        indexed_variables: variable.indexed_variables.map(|n| n as i64),
+        memory_reference: None,
+    }
+}
+
+/// a completion of the debugger as DAP shows it, `start` counts from 1
+fn dap_completion(completion: Completion) -> CompletionItem {
+    let type_field = match completion.kind {
+        CompletionKind::Variable => CompletionItemType::Variable,
+        CompletionKind::Property => CompletionItemType::Property,
+        CompletionKind::Function => CompletionItemType::Function,
+        CompletionKind::Module => CompletionItemType::Module,
+    };
+    CompletionItem {
+        label: completion.label,
+        text: None,
+        sort_text: None,
+        detail: None,
+        type_field: Some(type_field),
+        start: Some(completion.start as i64 + 1),
+        length: Some(completion.length as i64),
+        selection_start: None,
+        selection_length: None,
+    }
+}
+
+/// the exception the debugger is stopped at as DAP shows it, the context it
+/// was raised in are its inner exceptions, innermost first
+fn dap_exception_info(info: exceptions::ExceptionInfo) -> ExceptionInfoResponse {
+    let inner_exception = info
+        .context
+        .into_iter()
+        .map(|message| ExceptionDetails {
+            message: Some(message),
+            type_name: None,
+            full_type_name: None,
+            evaluate_name: None,
+            stack_trace: None,
+            inner_exception: None,
+        })
+        .collect();
+    let type_name = info.kind.error_kind();
+    ExceptionInfoResponse {
+        exception_id: info.kind.to_string(),
+        description: Some(info.description.clone()),
+        // errors tryEval catches are handled, execution only stopped at them
+        break_mode: if info.caught {
+            ExceptionBreakMode::Always
+        } else {
+            ExceptionBreakMode::Unhandled
+        },
+        details: Some(ExceptionDetails {
+            message: Some(info.description),
+            type_name: Some(type_name.to_string()),
+            full_type_name: Some(format!("tvix_eval::ErrorKind::{}", type_name)),
+            evaluate_name: None,
+            stack_trace: Some(info.excerpt),
+            inner_exception: Some(inner_exception),
+        }),
+    }
+}
+
+/// a file of the program as DAP shows it
+fn dap_source(file: String) -> Source {
+    Source {
+        name: Path::new(&file)
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned()),
+        path: Some(file),
+        source_reference: None,
+        presentation_hint: None,
+        origin: None,
+        sources: None,
+        adapter_data: None,
+        checksums: None,
+    }
+}
+
+/// a breakpoint of the debugger as DAP shows it, with the file it is in
+fn dap_breakpoint(entry: &BreakpointEntry) -> DapBreakpoint {
+    let source = match &entry.location {
+        Breakpoint::FileLine { file, .. } => Some(dap_source(file.to_string())),
+        _ => None,
+    };
+    DapBreakpoint {
+        source,
+        ..backend::dap_breakpoint(entry)
+    }
+}
+
+/// a frame of the debugger as DAP shows it, native frames are labels
+fn dap_stack_frame(frame: StackFrame) -> DapStackFrame {
+    let source = frame.file.map(dap_source);
+    let presentation_hint = match frame.kind {
+        FrameKind::Builtin => Some(StackFramePresentationhint::Label),
+        FrameKind::Generator => Some(StackFramePresentationhint::Subtle),
+        _ => None,
+    };
+    DapStackFrame {
+        id: frame.id as i64,
+        name: frame.name,
+        source,
+        line: frame.line.map_or(0, |line| line as i64),
+        column: frame.column.map_or(0, |column| column as i64),
+        end_line: frame.end_line.map(|line| line as i64),
+        end_column: frame.end_column.map(|column| column as i64),
+        can_restart: None,
+        instruction_pointer_reference: None,
+        module_id: None,
+        presentation_hint,
+    }
+}
+
+/// the granularity of a step request, statements if unspecified
+fn granularity(granularity: Option<DapSteppingGranularity>) -> SteppingGranularity {
+    match granularity {
+        Some(DapSteppingGranularity::Instruction) => SteppingGranularity::Instruction,
+        Some(DapSteppingGranularity::Line) => SteppingGranularity::Line,
+        Some(DapSteppingGranularity::Statement) | None => SteppingGranularity::Statement,
+    }
+}
+
+/// the context of an evaluate request, a repl one if unspecified or unknown.
+/// DAP allows contexts beyond the ones it names, so they are read as text
+fn evaluate_context(args: &EvaluateArguments) -> EvaluateContext {
+    serde_json::to_value(&args.context)
+        .ok()
+        .and_then(|context| context.as_str()?.parse().ok())
+        .unwrap_or_default()
+}
+
 impl<R, W> DebugAdapter for NixDebugAdapter<R, W>
 where
     R: AsyncRead + Unpin,
@@ -33,10 +234,56 @@ where
             ConfigurationDone => self.handle_configuration_done(seq).await,
             Launch(launch_args) => self.handle_launch(seq, launch_args).await,
             Disconnect(disconnect_args) => self.handle_disconnect(seq, disconnect_args).await,
+            Terminate(_) => self.handle_terminate(seq).await,
+            Continue(_) => {
+                let body = ResponseBody::Continue(ContinueResponse {
+                    all_threads_continued: Some(true),
+                });
+                self.handle_resume(seq, Command::Continue, body).await;
+            }
+            Next(next_args) => {
+                let command = Command::Next(granularity(next_args.granularity));
+                self.handle_resume(seq, command, ResponseBody::Next).await;
+            }
+            StepIn(step_in_args) => {
+                let command = Command::StepIn(granularity(step_in_args.granularity));
+                self.handle_resume(seq, command, ResponseBody::StepIn).await;
+            }
+            Pause(_) => self.handle_pause(seq).await,
+            StepOut(_) => {
+                self.handle_resume(seq, Command::StepOut, ResponseBody::StepOut)
+                    .await;
+            }
+            SetBreakpoints(set_breakpoints_args) => {
+                self.handle_set_breakpoints(seq, set_breakpoints_args).await;
+            }
+            SetFunctionBreakpoints(set_function_breakpoints_args) => {
+                self.handle_set_function_breakpoints(seq, set_function_breakpoints_args)
+                    .await;
+            }
+            SetExceptionBreakpoints(set_exception_breakpoints_args) => {
+                self.handle_set_exception_breakpoints(seq, set_exception_breakpoints_args)
+                    .await;
+            }
             BreakpointLocations(breakpoint_locations_args) => {
                 self.handle_breakpoint_locations(seq, breakpoint_locations_args)
                     .await;
             }
+            Threads => self.handle_threads(seq).await,
+            StackTrace(stack_trace_args) => self.handle_stack_trace(seq, stack_trace_args).await,
+            Scopes(scopes_args) => self.handle_scopes(seq, scopes_args).await,
+            Variables(variables_args) => self.handle_variables(seq, variables_args).await,
+            Evaluate(evaluate_args) => self.handle_evaluate(seq, evaluate_args).await,
+            Completions(completions_args) => {
+                self.handle_completions(seq, completions_args).await;
+            }
+            ExceptionInfo(_) => self.handle_exception_info(seq).await,
+            SetVariable(set_variable_args) => {
+                self.handle_set_variable(seq, set_variable_args).await;
+            }
+            SetExpression(set_expression_args) => {
+                self.handle_set_expression(seq, set_expression_args).await;
+            }
             _ => {
                 self.client
                     .send(Either::Right(Response {
@@ -58,13 +305,16 @@ where
 {
     /// handler for receipt of initialize event from client
     async fn handle_initialize(&mut self, seq: i64, _args: InitializeRequestArguments) {
-        let capabilities = default_capabilities();
-        let response = InitializeResponse { capabilities };
-
-        let body = Some(ResponseBody::Initialize(response));
         self.client.set_state(State::Initializing);
 
-        self.initialize_debugger();
+        self.initialize_debugger().await;
+
+        let capabilities = match self.request(Command::Initialize).await {
+            Some(CommandReply::InitializeReply(capabilities)) => capabilities,
+            _ => default_capabilities(),
+        };
+        let response = InitializeResponse { capabilities };
+        let body = Some(ResponseBody::Initialize(response));
 
         self.client
             .send(Either::Right(Response {
@@ -85,7 +335,15 @@ where
     }
 
     /// handler for receipt of configurationDone event from client
+    /// the breakpoints are set now, a launched program starts
     async fn handle_configuration_done(&mut self, seq: i64) {
+        self.state.configured = true;
+        if let Some((program, stop_on_entry)) = self.state.launch.take() {
+            if !self.start(program, stop_on_entry).await {
+                self.send_error(seq, "debugger is not initialized").await;
+                return;
+            }
+        }
         let body = Some(ResponseBody::ConfigurationDone);
         self.client
             .send(Either::Right(Response {
@@ -97,6 +355,24 @@ where
             .await;
     }
 
+    /// resume execution with `command`, answering the request with `body`
+    /// the program reports where it stops next with a stopped event
+    async fn handle_resume(&mut self, seq: i64, command: Command, body: ResponseBody) {
+        match self.request(command).await {
+            Some(_) => self.send_response(seq, body).await,
+            None => self.send_error(seq, "debugger is not initialized").await,
+        }
+    }
+
+    /// handler for pause request
+    /// the program stops at its next op and reports it with a stopped event
+    async fn handle_pause(&mut self, seq: i64) {
+        match self.request(Command::Pause).await {
+            Some(_) => self.send_response(seq, ResponseBody::Pause).await,
+            None => self.send_error(seq, "debugger is not initialized").await,
+        }
+    }
+
     /// handler for receipt of launch event from client
     async fn handle_launch(&mut self, seq: i64, args: ExtendedLaunchArguments) {
         let Some(_root_file) = args.inner.manifest.clone() else {
@@ -125,10 +401,19 @@ where
             return;
         };
 
-        println!("program is !! {}", args.program);
-        // TODO: this should happen in the initialization
-        println!("program initialized");
-
+        if self.server.is_none() {
+            self.send_error(seq, "debugger is not initialized").await;
+            return;
+        }
+        // the program runs once the client set its breakpoints
+        if self.state.configured {
+            if !self.start(args.program, args.stop_on_entry).await {
+                self.send_error(seq, "debugger is not initialized").await;
+                return;
+            }
+        } else {
+            self.state.launch = Some((args.program, args.stop_on_entry));
+        }
         // TODO some argument checking I think
         self.client
             .send(Either::Right(Response {
@@ -140,71 +425,461 @@ where
             .await;
     }
 
+    /// start evaluating `program`, returns whether the debugger took it
+    async fn start(&mut self, program: String, stop_on_entry: bool) -> bool {
+        let command = Command::Launch {
+            program: program.as_str().into(),
+            stop_on_entry,
+        };
+        self.request(command).await.is_some()
+    }
+
     /// handle disconnect request
-    /// terminates the debugger!
+    /// ends the session, the launched program is terminated unless asked to
+    /// let it run to its end
     async fn handle_disconnect(
         &mut self,
         seq: i64,
-        _disconnect_args: debug_types::requests::DisconnectArguments,
+        disconnect_args: debug_types::requests::DisconnectArguments,
     ) {
-        // blindly disconnect always
+        let command = Command::Disconnect {
+            terminate_debuggee: disconnect_args.terminate_debuggee.unwrap_or(true),
+        };
+        // the debugger ends its loop after replying
+        if let Some(reply) = self.request(command).await {
+            if !matches!(reply, CommandReply::DisconnectReply) {
+                error!("unexpected reply to disconnect: {reply:?}");
+            }
+        }
         self.client.set_state(State::ShutDown);
 
-        if let Some(server) = self.server.as_mut() {
+        if let Some(server) = self.server.take() {
             server
                 .shutdown
                 .store(true, std::sync::atomic::Ordering::SeqCst);
+            // a terminated program is gone once the debugger ends, a detached
+            // one keeps running on its own
+            let debugger = server.debugger;
+            let joined = tokio::task::spawn_blocking(move || debugger.join()).await;
+            if !matches!(joined, Ok(Ok(()))) {
+                error!("debugger thread panicked");
+            }
         }
 
-        let body = Some(ResponseBody::Disconnect);
+        self.send_response(seq, ResponseBody::Disconnect).await;
+    }
+
+    /// handle terminate request
+    /// stops the program, the session stays for another launch. The
+    /// terminated event comes once the program is gone
+    async fn handle_terminate(&mut self, seq: i64) {
+        match self.request(Command::Terminate).await {
+            Some(CommandReply::TerminateReply) => {
+                self.send_response(seq, ResponseBody::Terminate).await;
+            }
+            Some(reply) => {
+                error!("unexpected reply to terminate: {reply:?}");
+                self.send_error(seq, "unexpected reply from debugger").await;
+            }
+            None => self.send_error(seq, "debugger is not initialized").await,
+        }
+    }
+
+    /// handle set breakpoints request
+    /// replaces all the breakpoints of a source file
+    async fn handle_set_breakpoints(&mut self, seq: i64, args: SetBreakpointsArguments) {
+        match self.request(Command::SetBreakpoints(args)).await {
+            Some(CommandReply::SetBreakpointsReply(breakpoints)) => {
+                let body = ResponseBody::SetBreakpoints(SetBreakpointsResponse { breakpoints });
+                self.send_response(seq, body).await;
+            }
+            Some(reply) => {
+                error!("unexpected reply to setBreakpoints: {reply:?}");
+                self.send_error(seq, "unexpected reply from debugger").await;
+            }
+            None => self.send_error(seq, "debugger is not initialized").await,
+        }
+    }
+
+    /// send a command to the debugger server and wait for its reply
+    /// returns `None` if the server isn't running
+    async fn request(&mut self, command: Command) -> Option<CommandReply> {
+        let server = self.server.as_mut()?;
+        server.sender.send(command).await.ok()?;
+        server.receiver.recv().await
+    }
+
+    /// respond to a request successfully
+    async fn send_response(&mut self, seq: i64, body: ResponseBody) {
         self.client
             .send(Either::Right(Response {
                 request_seq: seq,
                 success: true,
                 message: None,
-                body,
+                body: Some(body),
+            }))
+            .await;
+    }
+
+    /// respond to a request with a failure
+    async fn send_error(&mut self, seq: i64, message: &str) {
+        self.client
+            .send(Either::Right(Response {
+                request_seq: seq,
+                success: false,
+                message: Some(message.to_string()),
+                body: None,
             }))
             .await;
     }
 
-    /// handle breapoint locataion request
-    async fn handle_breakpoint_locations(
+    /// handle set function breakpoints request
+    /// replaces all function breakpoints with the requested ones
+    async fn handle_set_function_breakpoints(
         &mut self,
-        _seq: i64,
-        // BreakpointLocationsArguments {
-        //     source,
-        //     line,
-        //     column,
-        //     end_line,
-        //     end_column,
-        // }: BreakpointLocationsArguments,
-        bruh: BreakpointLocationsArguments,
+        seq: i64,
+        args: SetFunctionBreakpointsArguments,
     ) {
-        error!("{:?}", bruh);
-        let _body = Some(ResponseBody::BreakpointLocations(
-            BreakpointLocationsResponse {
-                breakpoints: vec![BreakpointLocation {
-                    line: nll_todo(),
-                    column: nll_todo(),
-                    end_line: nll_todo(),
-                    end_column: nll_todo(),
-                }],
+        match self.request(Command::SetFunctionBreakpoints(args)).await {
+            Some(CommandReply::SetFunctionBreakpointsReply(breakpoints)) => {
+                let body = ResponseBody::SetFunctionBreakpoints(SetFunctionBreakpointsResponse {
+                    breakpoints,
+                });
+                self.send_response(seq, body).await;
+            }
+            Some(reply) => {
+                error!("unexpected reply to setFunctionBreakpoints: {reply:?}");
+                self.send_error(seq, "unexpected reply from debugger").await;
+            }
+            None => self.send_error(seq, "debugger is not initialized").await,
+        }
+    }
+
+    /// handle set exception breakpoints request
+    /// selects which evaluation errors stop execution
+    async fn handle_set_exception_breakpoints(
+        &mut self,
+        seq: i64,
+        args: SetExceptionBreakpointsArguments,
+    ) {
+        match self.request(Command::SetExceptionBreakpoints(args)).await {
+            Some(CommandReply::SetExceptionBreakpointsReply(breakpoints)) => {
+                let body = ResponseBody::SetExceptionBreakpoints(SetExceptionBreakpointsResponse {
+                    breakpoints: Some(breakpoints),
+                });
+                self.send_response(seq, body).await;
+            }
+            Some(reply) => {
+                error!("unexpected reply to setExceptionBreakpoints: {reply:?}");
+                self.send_error(seq, "unexpected reply from debugger").await;
+            }
+            None => self.send_error(seq, "debugger is not initialized").await,
+        }
+    }
+
+    /// handle breakpoint locations request
+    /// lists the places in the requested lines where execution can stop
+    async fn handle_breakpoint_locations(&mut self, seq: i64, args: BreakpointLocationsArguments) {
+        match self.request(Command::BreakpointLocations(args)).await {
+            Some(CommandReply::BreakpointLocationsReply(breakpoints)) => {
+                let body =
+                    ResponseBody::BreakpointLocations(BreakpointLocationsResponse { breakpoints });
+                self.send_response(seq, body).await;
+            }
+            Some(reply) => {
+                error!("unexpected reply to breakpointLocations: {reply:?}");
+                self.send_error(seq, "unexpected reply from debugger").await;
+            }
+            None => self.send_error(seq, "debugger is not initialized").await,
+        }
+    }
+
+    /// handle threads request
+    /// there is only the thread the evaluation runs on
+    async fn handle_threads(&mut self, seq: i64) {
+        let body = ResponseBody::Threads(ThreadsResponse {
+            threads: vec![Thread {
+                id: THREAD_ID,
+                name: "main".to_string(),
+            }],
+        });
+        self.send_response(seq, body).await;
+    }
+
+    /// handle stack trace request
+    /// lists the frames of the paused program from the top, native frames
+    /// of tvix are shown without a source
+    async fn handle_stack_trace(&mut self, seq: i64, args: StackTraceArguments) {
+        let command = Command::StackTrace {
+            start_frame: args.start_frame.map_or(0, |start| start.max(0) as usize),
+            levels: args.levels.map(|levels| levels.max(0) as usize),
+        };
+        match self.request(command).await {
+            Some(CommandReply::StackTraceReply { frames, total }) => {
+                let stack_frames = frames.into_iter().map(dap_stack_frame).collect();
+                let body = ResponseBody::StackTrace(StackTraceResponse {
+                    stack_frames,
+                    total_frames: Some(total as i64),
+                });
+                self.send_response(seq, body).await;
+            }
+            Some(reply) => {
+                error!("unexpected reply to stackTrace: {reply:?}");
+                self.send_error(seq, "unexpected reply from debugger").await;
+            }
+            None => self.send_error(seq, "debugger is not initialized").await,
+        }
+    }
+
+    /// handle scopes request
+    /// lists the scopes of a frame, their variables come with variables requests
+    async fn handle_scopes(&mut self, seq: i64, args: ScopesArguments) {
+        let frame_id = usize::try_from(args.frame_id).ok();
+        match self.request(Command::Scopes(frame_id)).await {
+            Some(CommandReply::ScopesReply(scopes)) => {
+                let scopes = scopes.into_iter().map(dap_scope).collect();
+                self.send_response(seq, ResponseBody::Scopes(ScopesResponse { scopes }))
+                    .await;
+            }
+            Some(reply) => {
+                error!("unexpected reply to scopes: {reply:?}");
+                self.send_error(seq, "unexpected reply from debugger").await;
+            }
+            None => self.send_error(seq, "debugger is not initialized").await,
+        }
+    }
+
+    /// handle variables request
+    /// lists the children of a scope or variable, a lazy variable (an
+    /// unforced thunk) is forced by listing it
+    async fn handle_variables(&mut self, seq: i64, args: VariablesArguments) {
+        let command = Command::Variables {
+            reference: args.variables_reference.max(0) as usize,
+            start: args.start.map_or(0, |start| start.max(0) as usize),
+            count: args.count.map(|count| count.max(0) as usize),
+        };
+        match self.request(command).await {
+            Some(CommandReply::VariablesReply(Ok(variables))) => {
+                let variables = variables.into_iter().map(dap_variable).collect();
+                let body = ResponseBody::Variables(VariablesResponse { variables });
+                self.send_response(seq, body).await;
+            }
+            Some(CommandReply::VariablesReply(Err(e))) => self.send_error(seq, &e).await,
+            Some(reply) => {
+                error!("unexpected reply to variables: {reply:?}");
+                self.send_error(seq, "unexpected reply from debugger").await;
+            }
+            None => self.send_error(seq, "debugger is not initialized").await,
+        }
+    }
+
+    /// handle evaluate request
+    /// evaluates an expression in the scope of a frame, hovers only look up
+    /// variables and their attributes
+    async fn handle_evaluate(&mut self, seq: i64, args: EvaluateArguments) {
+        let command = Command::Evaluate {
+            context: evaluate_context(&args),
+            frame_id: args.frame_id.and_then(|id| usize::try_from(id).ok()),
+            expression: args.expression.into(),
+        };
+        match self.request(command).await {
+            Some(CommandReply::EvaluateReply(Ok(variable))) => {
+                let variable = dap_variable(variable);
+                let body = ResponseBody::Evaluate(EvaluateResponse {
+                    result: variable.value,
+                    type_field: variable.type_field,
+                    presentation_hint: variable.presentation_hint,
+                    variables_reference: variable.variables_reference,
+                    named_variables: variable.named_variables,
+                    indexed_variables: variable.indexed_variables,
+                    memory_reference: None,
+                });
+                self.send_response(seq, body).await;
+            }
+            Some(CommandReply::EvaluateReply(Err(e))) => self.send_error(seq, &e).await,
+            Some(reply) => {
+                error!("unexpected reply to evaluate: {reply:?}");
+                self.send_error(seq, "unexpected reply from debugger").await;
+            }
+            None => self.send_error(seq, "debugger is not initialized").await,
+        }
+    }
+
+    /// handle completions request
+    /// completes names in scope and attributes of already evaluated sets,
+    /// columns count from 1
+    async fn handle_completions(&mut self, seq: i64, args: CompletionsArguments) {
+        // the column is on the requested line of a multi-line text
+        let text = match args.line {
+            Some(line) if line > 1 => args
+                .text
+                .lines()
+                .nth(line as usize - 1)
+                .unwrap_or_default()
+                .to_string(),
+            _ => args.text,
+        };
+        let command = Command::Completions {
+            text,
+            cursor: (args.column - 1).max(0) as usize,
+            frame_id: args.frame_id.and_then(|id| usize::try_from(id).ok()),
+        };
+        match self.request(command).await {
+            Some(CommandReply::CompletionsReply(completions)) => {
+                let targets = completions.into_iter().map(dap_completion).collect();
+                let body = ResponseBody::Completions(CompletionsResponse { targets });
+                self.send_response(seq, body).await;
+            }
+            Some(reply) => {
+                error!("unexpected reply to completions: {reply:?}");
+                self.send_error(seq, "unexpected reply from debugger").await;
+            }
+            None => self.send_error(seq, "debugger is not initialized").await,
+        }
+    }
+
+    /// handle exceptionInfo request
+    /// describes the error execution is stopped at, with its source excerpt
+    async fn handle_exception_info(&mut self, seq: i64) {
+        match self.request(Command::ExceptionInfo).await {
+            Some(CommandReply::ExceptionInfoReply(Ok(info))) => {
+                let body = ResponseBody::ExceptionInfo(dap_exception_info(info));
+                self.send_response(seq, body).await;
+            }
+            Some(CommandReply::ExceptionInfoReply(Err(e))) => self.send_error(seq, &e).await,
+            Some(reply) => {
+                error!("unexpected reply to exceptionInfo: {reply:?}");
+                self.send_error(seq, "unexpected reply from debugger").await;
+            }
+            None => self.send_error(seq, "debugger is not initialized").await,
+        }
+    }
+
+    /// handle setVariable request
+    /// replaces a variable nobody forced yet by the value of an expression
+    async fn handle_set_variable(&mut self, seq: i64, args: SetVariableArguments) {
+        let command = Command::SetVariable {
+            reference: args.variables_reference.max(0) as usize,
+            name: args.name,
+            value: args.value,
+        };
+        match self.request(command).await {
+            Some(CommandReply::SetVariableReply(Ok(variable))) => {
+                let variable = dap_variable(variable);
+                let body = ResponseBody::SetVariable(SetVariableResponse {
+                    value: variable.value,
+                    type_field: variable.type_field,
+                    variables_reference: Some(variable.variables_reference),
+                    named_variables: variable.named_variables,
+                    indexed_variables: variable.indexed_variables,
+                });
+                self.send_response(seq, body).await;
+            }
+            Some(CommandReply::SetVariableReply(Err(e))) => self.send_error(seq, &e).await,
+            Some(reply) => {
+                error!("unexpected reply to setVariable: {reply:?}");
+                self.send_error(seq, "unexpected reply from debugger").await;
+            }
+            None => self.send_error(seq, "debugger is not initialized").await,
+        }
+    }
+
+    /// handle setExpression request
+    /// replaces an attribute path nobody forced yet by the value of an
+    /// expression, evaluated in the scope of the frame
+    async fn handle_set_expression(&mut self, seq: i64, args: SetExpressionArguments) {
+        let command = Command::SetExpression {
+            expression: args.expression.into(),
+            value: args.value,
+            frame_id: args.frame_id.and_then(|id| usize::try_from(id).ok()),
+        };
+        match self.request(command).await {
+            Some(CommandReply::SetExpressionReply(Ok(variable))) => {
+                let variable = dap_variable(variable);
+                let body = ResponseBody::SetExpression(SetExpressionResponse {
+                    value: variable.value,
+                    type_field: variable.type_field,
+                    presentation_hint: variable.presentation_hint,
+                    variables_reference: Some(variable.variables_reference),
+                    named_variables: variable.named_variables,
+                    indexed_variables: variable.indexed_variables,
+                });
+                self.send_response(seq, body).await;
+            }
+            Some(CommandReply::SetExpressionReply(Err(e))) => self.send_error(seq, &e).await,
+            Some(reply) => {
+                error!("unexpected reply to setExpression: {reply:?}");
+                self.send_error(seq, "unexpected reply from debugger").await;
+            }
+            None => self.send_error(seq, "debugger is not initialized").await,
+        }
+    }
+
+    /// forward an event of the debugger to the client
+    pub async fn handle_event(&mut self, event: ObserverEvent) {
+        let body = match event {
+            ObserverEvent::Exited(exit_code) => {
+                self.client
+                    .send(Either::Left(EventBody::Exited { exit_code }))
+                    .await;
+                EventBody::Terminated { restart: None }
+            }
+            ObserverEvent::Terminated => EventBody::Terminated { restart: None },
+            ObserverEvent::Breakpoint(entry) => EventBody::Breakpoint {
+                reason: BreakpointEventReason::Changed,
+                breakpoint: dap_breakpoint(&entry),
             },
-        ));
-        nll_todo()
-        // self.client
-        // .send(Either::Right(Response {
-        //     request_seq: seq,
-        //     success: true,
-        //     message: None,
-        //     body,
-        // }))
-        // .await;
+            ObserverEvent::Output(output) => EventBody::Output {
+                category: None,
+                output: format!("{output}\n"),
+                group: None,
+                variables_reference: None,
+                source: None,
+                line: None,
+                column: None,
+                data: None,
+            },
+            ObserverEvent::Stopped {
+                reason,
+                description,
+                ..
+            } => {
+                let hit_breakpoint_ids: Vec<i64> = reason
+                    .hit_breakpoint_ids()
+                    .iter()
+                    .map(|id| *id as i64)
+                    .collect();
+                let (reason, text) = match reason {
+                    StopReason::Entry => (StoppedEventReason::Entry, None),
+                    StopReason::Step => (StoppedEventReason::Step, None),
+                    StopReason::Pause => (StoppedEventReason::Pause, None),
+                    StopReason::Breakpoint(_) => (StoppedEventReason::Breakpoint, None),
+                    StopReason::FunctionBreakpoint(_) => {
+                        (StoppedEventReason::FunctionBreakpoint, None)
+                    }
+                    StopReason::Exception(kind) => {
+                        (StoppedEventReason::Exception, Some(kind.to_string()))
+                    }
+                };
+                EventBody::Stopped {
+                    reason,
+                    description: Some(description),
+                    thread_id: Some(THREAD_ID),
+                    preserve_focus_hint: None,
+                    text,
+                    all_threads_stopped: Some(true),
+                    hit_breakpoint_ids: (!hit_breakpoint_ids.is_empty())
+                        .then_some(hit_breakpoint_ids),
+                }
+            }
+        };
+        self.client.send(Either::Left(body)).await;
     }
 
     async fn initialize_debugger(&mut self) {
         let (cmd_sender, mut cmd_receiver) = tokio::sync::mpsc::channel::<Command>(32);
         let (reply_sender, reply_receiver) = tokio::sync::mpsc::channel::<CommandReply>(32);
+        let (event_sender, event_receiver) = tokio::sync::mpsc::channel::<ObserverEvent>(32);
 
         let shutdown_token = Arc::new(AtomicBool::new(false));
         let shutdown_token_clone = shutdown_token.clone();
@@ -212,6 +887,17 @@ where
         let child = std::thread::spawn(move || {
             let args = Args::default();
             let mut debugger = tvix_debugger::backend::TvixBackend::new(args);
+            // forward the events of the observer, which come in while the
+            // program runs, independently of the commands
+            if let Some(events) = debugger.events() {
+                std::thread::spawn(move || {
+                    for event in events {
+                        if event_sender.blocking_send(event).is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
             while debugger.get_state() < DebuggerState::ShutDown
                 && !shutdown_token_clone.load(std::sync::atomic::Ordering::Relaxed)
             {
@@ -227,6 +913,7 @@ where
         self.server = Some(Server {
             sender: cmd_sender,
             receiver: reply_receiver,
+            events: event_receiver,
             debugger: child,
             shutdown: shutdown_token,
         });
@@ -250,5 +937,9 @@ where
 /// the debug state
 #[derive(Default, Debug, Clone)]
 pub struct NixDebugState {
-    // root_file: std::io
+    /// the program launched before the client was done configuring, and
+    /// whether to stop on its entry
+    launch: Option<(String, bool)>,
+    /// configurationDone was received, a launch starts the program at once
+    configured: bool,
 }
@@ -0,0 +1,37 @@
+mod common;
+
+use common::request::breakpoint_locations_request;
+use common::session::TestSession;
+
+use dawn_infra::dap_requests::ExtendedMessageKind;
+use debug_types::responses::ResponseBody;
+
+#[tokio::test]
+async fn test_breakpoint_locations() {
+    let mut session = TestSession::new().await;
+
+    let capabilities = session.initialize().await;
+    assert_eq!(
+        capabilities.supports_breakpoint_locations_request,
+        Some(true)
+    );
+
+    let request = breakpoint_locations_request("../tvix-debugger/tests/simple_fn_call.nix", 3, 3);
+    session.send(request).await;
+
+    let response = session.recv().await;
+    let locations = match response.message {
+        ExtendedMessageKind::Response(r) if r.success => match r.body {
+            Some(ResponseBody::BreakpointLocations(body)) => body.breakpoints,
+            other => panic!("unexpected breakpointLocations body: {:?}", other),
+        },
+        other => panic!("bad breakpointLocations response: {:?}", other),
+    };
+
+    // `greet = name: "Hello, ${name}!";` has the lambda body and the
+    // interpolated `name` to stop at
+    assert!(locations.len() >= 2);
+    assert!(locations.iter().all(|l| l.line == 3));
+
+    session.shutdown().await;
+}
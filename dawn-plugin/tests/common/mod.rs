@@ -1,2 +1,5 @@
+// every test crate compiles the helpers, and uses only some of them
+#![allow(dead_code)]
+
 pub mod request;
 pub mod session;
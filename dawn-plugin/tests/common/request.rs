@@ -36,6 +36,33 @@ pub fn launch_request_with_file(
     serde_json::from_value(val).expect("valid launch request (file)")
 }
 
+/// Builds a launch request that stops on the first op of the program.
+pub fn launch_request_stop_on_entry(program: &str) -> ExtendedProtocolMessage {
+    let val = json!({
+        "seq": 1,
+        "type": "request",
+        "command": "launch",
+        "arguments": {
+            "no_debug": false,
+            "manifest": ".",
+            "expression": "",
+            "program": program,
+            "stopOnEntry": true,
+        }
+    });
+    serde_json::from_value(val).expect("valid launch request (stop on entry)")
+}
+
+/// Builds a configurationDone request, which starts a launched program.
+pub fn configuration_done_request() -> ExtendedProtocolMessage {
+    let val = json!({
+        "seq": 1,
+        "type": "request",
+        "command": "configurationDone",
+    });
+    serde_json::from_value(val).expect("valid configurationDone request")
+}
+
 /// Builds a disconnect request.
 pub fn disconnect_request() -> ExtendedProtocolMessage {
     let val = json!({
@@ -48,3 +75,260 @@ pub fn disconnect_request() -> ExtendedProtocolMessage {
     });
     serde_json::from_value(val).expect("valid disconnect request")
 }
+
+/// Builds a setBreakpoints request for the given source lines.
+pub fn set_breakpoints_request(path: &str, lines: &[i64]) -> ExtendedProtocolMessage {
+    let breakpoints: Vec<_> = lines.iter().map(|line| json!({ "line": line })).collect();
+    let val = json!({
+        "seq": 1,
+        "type": "request",
+        "command": "setBreakpoints",
+        "arguments": {
+            "source": { "path": path },
+            "breakpoints": breakpoints,
+        }
+    });
+    serde_json::from_value(val).expect("valid setBreakpoints request")
+}
+
+/// Builds a setBreakpoints request with a single logpoint.
+pub fn set_logpoint_request(path: &str, line: i64, message: &str) -> ExtendedProtocolMessage {
+    let val = json!({
+        "seq": 1,
+        "type": "request",
+        "command": "setBreakpoints",
+        "arguments": {
+            "source": { "path": path },
+            "breakpoints": [{ "line": line, "logMessage": message }],
+        }
+    });
+    serde_json::from_value(val).expect("valid setBreakpoints request")
+}
+
+/// Builds a setExceptionBreakpoints request for the given filter ids.
+pub fn set_exception_breakpoints_request(filters: &[&str]) -> ExtendedProtocolMessage {
+    let val = json!({
+        "seq": 1,
+        "type": "request",
+        "command": "setExceptionBreakpoints",
+        "arguments": {
+            "filters": filters,
+        }
+    });
+    serde_json::from_value(val).expect("valid setExceptionBreakpoints request")
+}
+
+/// Builds a breakpointLocations request for a range of source lines.
+pub fn breakpoint_locations_request(
+    path: &str,
+    line: i64,
+    end_line: i64,
+) -> ExtendedProtocolMessage {
+    let val = json!({
+        "seq": 1,
+        "type": "request",
+        "command": "breakpointLocations",
+        "arguments": {
+            "source": { "path": path },
+            "line": line,
+            "endLine": end_line,
+        }
+    });
+    serde_json::from_value(val).expect("valid breakpointLocations request")
+}
+
+/// Builds a setFunctionBreakpoints request for the given function names.
+pub fn set_function_breakpoints_request(names: &[&str]) -> ExtendedProtocolMessage {
+    let breakpoints: Vec<_> = names.iter().map(|name| json!({ "name": name })).collect();
+    let val = json!({
+        "seq": 1,
+        "type": "request",
+        "command": "setFunctionBreakpoints",
+        "arguments": {
+            "breakpoints": breakpoints,
+        }
+    });
+    serde_json::from_value(val).expect("valid setFunctionBreakpoints request")
+}
+
+/// Builds a continue request for the only thread.
+pub fn continue_request() -> ExtendedProtocolMessage {
+    let val = json!({
+        "seq": 1,
+        "type": "request",
+        "command": "continue",
+        "arguments": {
+            "threadId": 1,
+        }
+    });
+    serde_json::from_value(val).expect("valid continue request")
+}
+
+/// Builds a next request, stepping over calls by `granularity`.
+pub fn next_request(granularity: Option<&str>) -> ExtendedProtocolMessage {
+    let val = json!({
+        "seq": 1,
+        "type": "request",
+        "command": "next",
+        "arguments": {
+            "threadId": 1,
+            "granularity": granularity,
+        }
+    });
+    serde_json::from_value(val).expect("valid next request")
+}
+
+/// Builds a stepIn request.
+pub fn step_in_request() -> ExtendedProtocolMessage {
+    let val = json!({
+        "seq": 1,
+        "type": "request",
+        "command": "stepIn",
+        "arguments": {
+            "threadId": 1,
+        }
+    });
+    serde_json::from_value(val).expect("valid stepIn request")
+}
+
+/// Builds a stepOut request.
+pub fn step_out_request() -> ExtendedProtocolMessage {
+    let val = json!({
+        "seq": 1,
+        "type": "request",
+        "command": "stepOut",
+        "arguments": {
+            "threadId": 1,
+        }
+    });
+    serde_json::from_value(val).expect("valid stepOut request")
+}
+
+/// Builds a pause request.
+pub fn pause_request() -> ExtendedProtocolMessage {
+    let val = json!({
+        "seq": 1,
+        "type": "request",
+        "command": "pause",
+        "arguments": {
+            "threadId": 1,
+        }
+    });
+    serde_json::from_value(val).expect("valid pause request")
+}
+
+/// Builds an exceptionInfo request.
+pub fn exception_info_request() -> ExtendedProtocolMessage {
+    let val = json!({
+        "seq": 1,
+        "type": "request",
+        "command": "exceptionInfo",
+        "arguments": {
+            "threadId": 1,
+        }
+    });
+    serde_json::from_value(val).expect("valid exceptionInfo request")
+}
+
+/// Builds a stackTrace request for the only thread.
+pub fn stack_trace_request(start_frame: i64, levels: i64) -> ExtendedProtocolMessage {
+    let val = json!({
+        "seq": 1,
+        "type": "request",
+        "command": "stackTrace",
+        "arguments": {
+            "threadId": 1,
+            "startFrame": start_frame,
+            "levels": levels,
+        }
+    });
+    serde_json::from_value(val).expect("valid stackTrace request")
+}
+
+/// Builds a scopes request for a frame.
+pub fn scopes_request(frame_id: i64) -> ExtendedProtocolMessage {
+    let val = json!({
+        "seq": 1,
+        "type": "request",
+        "command": "scopes",
+        "arguments": {
+            "frameId": frame_id,
+        }
+    });
+    serde_json::from_value(val).expect("valid scopes request")
+}
+
+/// Builds a variables request for a page of the children of a reference.
+pub fn variables_request(reference: i64, start: i64, count: i64) -> ExtendedProtocolMessage {
+    let val = json!({
+        "seq": 1,
+        "type": "request",
+        "command": "variables",
+        "arguments": {
+            "variablesReference": reference,
+            "start": start,
+            "count": count,
+        }
+    });
+    serde_json::from_value(val).expect("valid variables request")
+}
+
+/// Builds an evaluate request for an expression in the scope of a frame.
+pub fn evaluate_request(expression: &str, frame_id: i64, context: &str) -> ExtendedProtocolMessage {
+    let val = json!({
+        "seq": 1,
+        "type": "request",
+        "command": "evaluate",
+        "arguments": {
+            "expression": expression,
+            "frameId": frame_id,
+            "context": context,
+        }
+    });
+    serde_json::from_value(val).expect("valid evaluate request")
+}
+
+/// Builds a completions request for the text before a column of the console.
+pub fn completions_request(text: &str, column: i64, frame_id: i64) -> ExtendedProtocolMessage {
+    let val = json!({
+        "seq": 1,
+        "type": "request",
+        "command": "completions",
+        "arguments": {
+            "text": text,
+            "column": column,
+            "frameId": frame_id,
+        }
+    });
+    serde_json::from_value(val).expect("valid completions request")
+}
+
+/// Builds a setExpression request overriding an attribute path in a frame.
+pub fn set_expression_request(
+    expression: &str,
+    value: &str,
+    frame_id: i64,
+) -> ExtendedProtocolMessage {
+    let val = json!({
+        "seq": 1,
+        "type": "request",
+        "command": "setExpression",
+        "arguments": {
+            "expression": expression,
+            "value": value,
+            "frameId": frame_id,
+        }
+    });
+    serde_json::from_value(val).expect("valid setExpression request")
+}
+
+/// Builds a terminate request.
+pub fn terminate_request() -> ExtendedProtocolMessage {
+    let val = json!({
+        "seq": 1,
+        "type": "request",
+        "command": "terminate",
+        "arguments": {}
+    });
+    serde_json::from_value(val).expect("valid terminate request")
+}
@@ -8,8 +8,16 @@ use dawn_infra::{
     codec::DebugAdapterCodec,
     dap_requests::{ExtendedMessageKind, ExtendedProtocolMessage},
 };
+use debug_types::{
+    events::{EventBody, StoppedEventReason},
+    responses::{Response, ResponseBody},
+    types::{Capabilities, StackFrame},
+};
 
-use super::request::disconnect_request;
+use super::request::{
+    configuration_done_request, disconnect_request, initialize_request,
+    launch_request_stop_on_entry, stack_trace_request,
+};
 
 /// Holds the full state of a test session.
 pub struct TestSession {
@@ -48,17 +56,115 @@ impl TestSession {
             .expect("decode error")
     }
 
-    /// Gracefully shutdown the adapter thread.
-    pub async fn shutdown(mut self) {
-        // TODO: send terminate with a timeout, then disconnect
-        // currently just forces disconnect immediately
+    /// Initializes the adapter, returns its capabilities once the
+    /// initialized event followed them.
+    pub async fn initialize(&mut self) -> Capabilities {
+        self.send(initialize_request()).await;
+        let capabilities = match self.recv().await.message {
+            ExtendedMessageKind::Response(r) if r.success => match r.body {
+                Some(ResponseBody::Initialize(init)) => init.capabilities,
+                other => panic!("unexpected initialize body: {:?}", other),
+            },
+            other => panic!("bad initialize response: {:?}", other),
+        };
+        match self.recv().await.message {
+            ExtendedMessageKind::Event(e) if matches!(e.body, Some(EventBody::Initialized {})) => {}
+            other => panic!("expected the initialized event: {:?}", other),
+        }
+        capabilities
+    }
 
-        self.send(disconnect_request()).await;
+    /// Sends a launch request, then configurationDone which starts the
+    /// program, and checks that both succeed.
+    pub async fn launch(&mut self, launch: ExtendedProtocolMessage) {
+        self.send(launch).await;
+        match self.recv().await.message {
+            ExtendedMessageKind::Response(r) if r.success => {}
+            other => panic!("bad launch response: {:?}", other),
+        }
+        self.send(configuration_done_request()).await;
+        match self.recv().await.message {
+            ExtendedMessageKind::Response(r) if r.success => {}
+            other => panic!("bad configurationDone response: {:?}", other),
+        }
+    }
+
+    /// Initializes the adapter and launches `program`, returns once it is
+    /// stopped on its first op.
+    pub async fn start_on_entry(&mut self, program: &str) {
+        self.initialize().await;
+        self.launch(launch_request_stop_on_entry(program)).await;
+        match self.recv().await.message {
+            ExtendedMessageKind::Event(e) if matches!(e.body, Some(EventBody::Stopped { .. })) => {}
+            other => panic!("expected a stopped event: {:?}", other),
+        }
+    }
+
+    /// Waits for the response to the last request, skipping the events of
+    /// the program.
+    pub async fn response(&mut self) -> Response {
+        loop {
+            match self.recv().await.message {
+                ExtendedMessageKind::Response(r) => return r,
+                ExtendedMessageKind::Event(_) => continue,
+                other => panic!("expected a response: {:?}", other),
+            }
+        }
+    }
+
+    /// Waits for the first event `wanted` accepts, skipping the others.
+    pub async fn event(&mut self, wanted: impl Fn(&EventBody) -> bool) -> EventBody {
+        loop {
+            match self.recv().await.message {
+                ExtendedMessageKind::Event(e) => match e.body {
+                    Some(body) if wanted(&body) => return body,
+                    _ => continue,
+                },
+                other => panic!("expected an event: {:?}", other),
+            }
+        }
+    }
+
+    /// Waits for the program to stop, returns why with the frame it
+    /// stopped in.
+    pub async fn stopped(&mut self) -> (StoppedEventReason, StackFrame) {
+        let reason = match self.event(|e| matches!(e, EventBody::Stopped { .. })).await {
+            EventBody::Stopped { reason, .. } => reason,
+            other => panic!("expected a stopped event: {:?}", other),
+        };
+        self.send(stack_trace_request(0, 1)).await;
+        let frame = match self.response().await {
+            Response {
+                success: true,
+                body: Some(ResponseBody::StackTrace(body)),
+                ..
+            } => body.stack_frames.into_iter().next().expect("a frame"),
+            other => panic!("bad stackTrace response: {:?}", other),
+        };
+        (reason, frame)
+    }
 
-        // let response = self.recv().await;
-        // match response.message {
-        //     ExtendedMessageKind::Response(r) if r.success => {}
-        //     other => panic!("bad disconnect response: {:?}", other),
-        // }
+    /// Waits for the program to end, returns its exit code.
+    pub async fn exited(&mut self) -> i64 {
+        match self.event(|e| matches!(e, EventBody::Exited { .. })).await {
+            EventBody::Exited { exit_code } => exit_code,
+            other => panic!("expected an exited event: {:?}", other),
+        }
+    }
+
+    /// Disconnects, which ends the adapter, and checks the response.
+    pub async fn shutdown(mut self) {
+        self.send(disconnect_request()).await;
+        // events of the ending program may come first
+        loop {
+            match self.recv().await.message {
+                ExtendedMessageKind::Response(r) => {
+                    assert!(r.success, "bad disconnect response: {:?}", r);
+                    break;
+                }
+                ExtendedMessageKind::Event(_) => continue,
+                other => panic!("expected the disconnect response: {:?}", other),
+            }
+        }
     }
 }
@@ -0,0 +1,54 @@
+mod common;
+
+use common::request::{completions_request, launch_request_stop_on_entry, stack_trace_request};
+use common::session::TestSession;
+
+use dawn_infra::dap_requests::ExtendedMessageKind;
+use debug_types::responses::ResponseBody;
+
+#[tokio::test]
+async fn test_completions_of_builtins() {
+    let mut session = TestSession::new().await;
+
+    let capabilities = session.initialize().await;
+    assert_eq!(
+        capabilities.completion_trigger_characters,
+        Some(vec![".".to_string()])
+    );
+
+    session
+        .launch(launch_request_stop_on_entry(
+            "../tvix-debugger/tests/simple.nix",
+        ))
+        .await;
+    let _stopped = session.recv().await;
+
+    session.send(stack_trace_request(0, 1)).await;
+    let frame_id = match session.recv().await.message {
+        ExtendedMessageKind::Response(r) => match r.body {
+            Some(ResponseBody::StackTrace(body)) => body.stack_frames[0].id,
+            other => panic!("unexpected body: {:?}", other),
+        },
+        other => panic!("bad stackTrace response: {:?}", other),
+    };
+
+    session
+        .send(completions_request("builtins.leng", 14, frame_id))
+        .await;
+    match session.recv().await.message {
+        ExtendedMessageKind::Response(r) if r.success => match r.body {
+            Some(ResponseBody::Completions(body)) => {
+                let length = body
+                    .targets
+                    .iter()
+                    .find(|target| target.label == "length")
+                    .expect("builtins.length is completed");
+                assert_eq!((length.start, length.length), (Some(10), Some(4)));
+            }
+            other => panic!("unexpected body: {:?}", other),
+        },
+        other => panic!("bad completions response: {:?}", other),
+    }
+
+    session.shutdown().await;
+}
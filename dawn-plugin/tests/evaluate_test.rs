@@ -0,0 +1,110 @@
+mod common;
+
+use common::request::{
+    evaluate_request, launch_request_with_file, scopes_request, set_breakpoints_request,
+    stack_trace_request, variables_request,
+};
+use common::session::TestSession;
+
+use dawn_infra::dap_requests::ExtendedMessageKind;
+use debug_types::events::StoppedEventReason;
+use debug_types::responses::{Response, ResponseBody};
+
+#[tokio::test]
+async fn test_evaluate_in_frame_scope() {
+    let mut session = TestSession::new().await;
+
+    session
+        .start_on_entry("../tvix-debugger/tests/simple.nix")
+        .await;
+
+    session.send(stack_trace_request(0, 1)).await;
+    let frame_id = match session.recv().await.message {
+        ExtendedMessageKind::Response(r) => match r.body {
+            Some(ResponseBody::StackTrace(body)) => body.stack_frames[0].id,
+            other => panic!("unexpected body: {:?}", other),
+        },
+        other => panic!("bad stackTrace response: {:?}", other),
+    };
+
+    session
+        .send(evaluate_request("{ x = [ 1 2 ]; }", frame_id, "repl"))
+        .await;
+    match session.recv().await.message {
+        ExtendedMessageKind::Response(r) if r.success => match r.body {
+            Some(ResponseBody::Evaluate(body)) => {
+                assert_eq!(body.result, "{ x = [ 1 2 ]; }");
+                assert!(body.variables_reference > 0);
+            }
+            other => panic!("unexpected body: {:?}", other),
+        },
+        other => panic!("bad evaluate response: {:?}", other),
+    }
+
+    // hovering only looks up variables, it doesn't run code
+    session
+        .send(evaluate_request("builtins.length [ ]", frame_id, "hover"))
+        .await;
+    match session.recv().await.message {
+        ExtendedMessageKind::Response(r) => assert!(!r.success),
+        other => panic!("expected a response: {:?}", other),
+    }
+
+    session.shutdown().await;
+}
+
+#[tokio::test]
+async fn test_hover_leaves_unforced_bindings_lazy() {
+    let mut session = TestSession::new().await;
+
+    session.initialize().await;
+    session
+        .send(set_breakpoints_request(
+            "../tvix-debugger/tests/override.nix",
+            &[4],
+        ))
+        .await;
+    let _breakpoints = session.response().await;
+    session
+        .launch(launch_request_with_file(
+            "../tvix-debugger/tests/override.nix",
+            None,
+        ))
+        .await;
+
+    // at the assertion, before anything forced `a`
+    let (reason, frame) = session.stopped().await;
+    assert_eq!(reason, StoppedEventReason::Breakpoint);
+
+    session.send(evaluate_request("a", frame.id, "hover")).await;
+    match session.response().await {
+        Response {
+            success: true,
+            body: Some(ResponseBody::Evaluate(body)),
+            ..
+        } => assert_eq!(body.result, "<not yet evaluated>"),
+        other => panic!("bad evaluate response: {:?}", other),
+    }
+
+    // the hover didn't force it, it is still lazy
+    session.send(scopes_request(frame.id)).await;
+    let locals = match session.response().await.body {
+        Some(ResponseBody::Scopes(body)) => body.scopes[0].variables_reference,
+        other => panic!("unexpected body: {:?}", other),
+    };
+    session.send(variables_request(locals, 0, 0)).await;
+    let variables = match session.response().await.body {
+        Some(ResponseBody::Variables(body)) => body.variables,
+        other => panic!("unexpected body: {:?}", other),
+    };
+    let a = variables
+        .iter()
+        .find(|v| v.name == "a")
+        .expect("a is a local");
+    assert_eq!(
+        a.presentation_hint.as_ref().and_then(|hint| hint.lazy),
+        Some(true)
+    );
+
+    session.shutdown().await;
+}
@@ -0,0 +1,58 @@
+mod common;
+
+use common::request::{
+    exception_info_request, launch_request_with_file, set_exception_breakpoints_request,
+};
+use common::session::TestSession;
+
+use debug_types::events::StoppedEventReason;
+use debug_types::responses::{Response, ResponseBody};
+use debug_types::types::ExceptionBreakMode;
+
+const PROGRAM: &str = "../tvix-debugger/tests/throw.nix";
+
+/// Launches the program, stopped where it throws.
+async fn stop_at_throw(session: &mut TestSession) {
+    session.initialize().await;
+    session
+        .send(set_exception_breakpoints_request(&["throw"]))
+        .await;
+    assert!(session.response().await.success);
+    session
+        .launch(launch_request_with_file(PROGRAM, None))
+        .await;
+
+    let (reason, frame) = session.stopped().await;
+    assert_eq!(reason, StoppedEventReason::Exception);
+    assert_eq!(frame.line, 2);
+    assert_eq!(frame.name, "fail");
+}
+
+#[tokio::test]
+async fn test_stop_on_throw() {
+    let mut session = TestSession::new().await;
+    stop_at_throw(&mut session).await;
+    session.shutdown().await;
+}
+
+#[tokio::test]
+async fn test_exception_info_of_throw() {
+    let mut session = TestSession::new().await;
+    stop_at_throw(&mut session).await;
+
+    session.send(exception_info_request()).await;
+    let info = match session.response().await {
+        Response {
+            success: true,
+            body: Some(ResponseBody::ExceptionInfo(body)),
+            ..
+        } => body,
+        other => panic!("bad exceptionInfo response: {:?}", other),
+    };
+    assert_eq!(info.exception_id, "throw");
+    assert!(info.description.expect("a description").contains("boom"));
+    // nothing catches it
+    assert!(matches!(info.break_mode, ExceptionBreakMode::Unhandled));
+
+    session.shutdown().await;
+}
@@ -1,31 +1,80 @@
 mod common;
 
-use common::request::{initialize_request, launch_request_with_file};
+use common::request::{
+    configuration_done_request, launch_request_stop_on_entry, launch_request_with_file,
+};
 use common::session::TestSession;
 
 use dawn_infra::dap_requests::ExtendedMessageKind;
+use debug_types::events::{EventBody, StoppedEventReason};
 
 #[tokio::test]
 async fn test_launch_request_file() {
     let mut session = TestSession::new().await;
 
-    session.send(initialize_request()).await;
-    let _capabilities = session.recv().await;
-    println!("Checkpoint: capabilities done");
-    let _initialized = session.recv().await;
-    println!("Checkpoint: initialized done");
-
-    let launch_request =
-        launch_request_with_file("../tvix-debugger/tests/simple.nix", Some(".".into()));
-    session.send(launch_request).await;
-    println!("Checkpoint: launched");
-
-    let response = session.recv().await;
-    println!("Checkpoint: got reply from session");
-    match response.message {
-        ExtendedMessageKind::Response(r) if r.success => {}
+    session.initialize().await;
+    session
+        .launch(launch_request_with_file(
+            "../tvix-debugger/tests/simple.nix",
+            Some(".".into()),
+        ))
+        .await;
+
+    session.shutdown().await;
+}
+
+#[tokio::test]
+async fn test_launch_stop_on_entry() {
+    let mut session = TestSession::new().await;
+
+    session.initialize().await;
+    session
+        .launch(launch_request_stop_on_entry(
+            "../tvix-debugger/tests/simple.nix",
+        ))
+        .await;
+
+    let stopped = session.recv().await;
+    match stopped.message {
+        ExtendedMessageKind::Event(e) => match e.body {
+            Some(EventBody::Stopped { reason, .. }) => {
+                assert_eq!(reason, StoppedEventReason::Entry)
+            }
+            other => panic!("unexpected event: {:?}", other),
+        },
+        other => panic!("expected a stopped event: {:?}", other),
+    }
+
+    session.shutdown().await;
+}
+
+#[tokio::test]
+async fn test_launch_waits_for_configuration_done() {
+    let mut session = TestSession::new().await;
+
+    session.initialize().await;
+    session
+        .send(launch_request_stop_on_entry(
+            "../tvix-debugger/tests/simple.nix",
+        ))
+        .await;
+    match session.recv().await.message {
+        ExtendedMessageKind::Response(r) => assert!(r.success),
         other => panic!("bad launch response: {:?}", other),
     }
 
+    // the program only starts, and stops on its entry, once configured
+    session.send(configuration_done_request()).await;
+    match session.recv().await.message {
+        ExtendedMessageKind::Response(r) => assert!(r.success),
+        other => panic!("expected the configurationDone response: {:?}", other),
+    }
+    match session.recv().await.message {
+        ExtendedMessageKind::Event(e) => {
+            assert!(matches!(e.body, Some(EventBody::Stopped { .. })))
+        }
+        other => panic!("expected a stopped event: {:?}", other),
+    }
+
     session.shutdown().await;
 }
@@ -0,0 +1,29 @@
+mod common;
+
+use common::request::{launch_request_with_file, pause_request};
+use common::session::TestSession;
+
+use debug_types::events::StoppedEventReason;
+
+#[tokio::test]
+async fn test_pause_running_program() {
+    let mut session = TestSession::new().await;
+
+    session.initialize().await;
+    // runs far longer than the test, unless it is paused
+    session
+        .launch(launch_request_with_file(
+            "../tvix-debugger/tests/fib.nix",
+            None,
+        ))
+        .await;
+
+    session.send(pause_request()).await;
+    assert!(session.response().await.success);
+
+    let (reason, frame) = session.stopped().await;
+    assert_eq!(reason, StoppedEventReason::Pause);
+    assert_eq!(frame.line, 2);
+
+    session.shutdown().await;
+}
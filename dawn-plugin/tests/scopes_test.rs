@@ -0,0 +1,41 @@
+mod common;
+
+use common::request::{scopes_request, stack_trace_request};
+use common::session::TestSession;
+
+use dawn_infra::dap_requests::ExtendedMessageKind;
+use debug_types::responses::ResponseBody;
+
+#[tokio::test]
+async fn test_scopes_of_top_frame() {
+    let mut session = TestSession::new().await;
+
+    session
+        .start_on_entry("../tvix-debugger/tests/simple.nix")
+        .await;
+
+    session.send(stack_trace_request(0, 1)).await;
+    let frame_id = match session.recv().await.message {
+        ExtendedMessageKind::Response(r) => match r.body {
+            Some(ResponseBody::StackTrace(body)) => body.stack_frames[0].id,
+            other => panic!("unexpected body: {:?}", other),
+        },
+        other => panic!("bad stackTrace response: {:?}", other),
+    };
+
+    session.send(scopes_request(frame_id)).await;
+    let scopes = match session.recv().await.message {
+        ExtendedMessageKind::Response(r) if r.success => match r.body {
+            Some(ResponseBody::Scopes(body)) => body.scopes,
+            other => panic!("unexpected body: {:?}", other),
+        },
+        other => panic!("bad scopes response: {:?}", other),
+    };
+
+    let names: Vec<&str> = scopes.iter().map(|scope| scope.name.as_str()).collect();
+    assert_eq!(names.first(), Some(&"Locals"));
+    assert_eq!(names.last(), Some(&"Builtins"));
+    assert!(scopes.iter().all(|scope| scope.variables_reference > 0));
+
+    session.shutdown().await;
+}
@@ -0,0 +1,72 @@
+mod common;
+
+use common::request::{launch_request_with_file, set_breakpoints_request, set_logpoint_request};
+use common::session::TestSession;
+
+use dawn_infra::dap_requests::ExtendedMessageKind;
+use debug_types::events::EventBody;
+use debug_types::responses::ResponseBody;
+
+#[tokio::test]
+async fn test_set_breakpoints_verifies_lines() {
+    let mut session = TestSession::new().await;
+
+    session.initialize().await;
+
+    let request = set_breakpoints_request("../tvix-debugger/tests/simple.nix", &[2, 3, 10]);
+    session.send(request).await;
+
+    let response = session.recv().await;
+    let breakpoints = match response.message {
+        ExtendedMessageKind::Response(r) if r.success => match r.body {
+            Some(ResponseBody::SetBreakpoints(body)) => body.breakpoints,
+            other => panic!("unexpected setBreakpoints body: {:?}", other),
+        },
+        other => panic!("bad setBreakpoints response: {:?}", other),
+    };
+
+    assert_eq!(breakpoints.len(), 3);
+    // `a = 3 + 1;` has code
+    assert!(breakpoints[0].verified);
+    assert_eq!(breakpoints[0].line, Some(2));
+    // `in` has none, the breakpoint moves to the body of the let
+    assert!(breakpoints[1].verified);
+    assert_eq!(breakpoints[1].line, Some(4));
+    // past the end of the file
+    assert!(!breakpoints[2].verified);
+
+    session.shutdown().await;
+}
+
+#[tokio::test]
+async fn test_logpoint_prints_and_runs_on() {
+    let mut session = TestSession::new().await;
+
+    session.initialize().await;
+    session
+        .send(set_logpoint_request(
+            "../tvix-debugger/tests/stepping.nix",
+            8,
+            "a is {a}",
+        ))
+        .await;
+    assert!(session.response().await.success);
+    session
+        .launch(launch_request_with_file(
+            "../tvix-debugger/tests/stepping.nix",
+            None,
+        ))
+        .await;
+
+    match session
+        .event(|e| matches!(e, EventBody::Output { .. }))
+        .await
+    {
+        EventBody::Output { output, .. } => assert_eq!(output, "a is 2\n"),
+        other => panic!("expected an output event: {:?}", other),
+    }
+    // a logpoint never stops, the program runs to its end
+    assert_eq!(session.exited().await, 0);
+
+    session.shutdown().await;
+}
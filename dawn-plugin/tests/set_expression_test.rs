@@ -0,0 +1,114 @@
+mod common;
+
+use common::request::{
+    continue_request, launch_request_stop_on_entry, launch_request_with_file, scopes_request,
+    set_breakpoints_request, set_expression_request, stack_trace_request, variables_request,
+};
+use common::session::TestSession;
+
+use dawn_infra::dap_requests::ExtendedMessageKind;
+use debug_types::{
+    events::StoppedEventReason,
+    responses::{Response, ResponseBody},
+};
+
+#[tokio::test]
+async fn test_set_expression_only_overrides_variables() {
+    let mut session = TestSession::new().await;
+
+    let capabilities = session.initialize().await;
+    assert_eq!(capabilities.supports_set_expression, Some(true));
+    assert_eq!(capabilities.supports_set_variable, Some(true));
+
+    session
+        .launch(launch_request_stop_on_entry(
+            "../tvix-debugger/tests/simple.nix",
+        ))
+        .await;
+    let _stopped = session.recv().await;
+
+    session.send(stack_trace_request(0, 1)).await;
+    let frame_id = match session.recv().await.message {
+        ExtendedMessageKind::Response(r) => match r.body {
+            Some(ResponseBody::StackTrace(body)) => body.stack_frames[0].id,
+            other => panic!("unexpected body: {:?}", other),
+        },
+        other => panic!("bad stackTrace response: {:?}", other),
+    };
+
+    // the result of a call is no variable that could be overridden
+    session
+        .send(set_expression_request("builtins.head [ 1 ]", "2", frame_id))
+        .await;
+    match session.recv().await.message {
+        ExtendedMessageKind::Response(r) => assert!(!r.success),
+        other => panic!("expected a response: {:?}", other),
+    }
+
+    session.shutdown().await;
+}
+
+#[tokio::test]
+async fn test_set_expression_overrides_unforced_variable() {
+    let mut session = TestSession::new().await;
+
+    session.initialize().await;
+    session
+        .send(set_breakpoints_request(
+            "../tvix-debugger/tests/override.nix",
+            &[4],
+        ))
+        .await;
+    let _breakpoints = session.response().await;
+    session
+        .launch(launch_request_with_file(
+            "../tvix-debugger/tests/override.nix",
+            None,
+        ))
+        .await;
+
+    // at the assertion, before anything forced `a`
+    let (reason, frame) = session.stopped().await;
+    assert_eq!(reason, StoppedEventReason::Breakpoint);
+    assert_eq!(frame.line, 4);
+
+    session
+        .send(set_expression_request("a", "10", frame.id))
+        .await;
+    match session.response().await {
+        Response {
+            success: true,
+            body: Some(ResponseBody::SetExpression(body)),
+            ..
+        } => {
+            assert_eq!(body.value, "10");
+            assert_eq!(body.type_field.as_deref(), Some("int (overridden)"));
+        }
+        other => panic!("bad setExpression response: {:?}", other),
+    }
+
+    session.send(scopes_request(frame.id)).await;
+    let locals = match session.response().await.body {
+        Some(ResponseBody::Scopes(body)) => body.scopes[0].variables_reference,
+        other => panic!("unexpected body: {:?}", other),
+    };
+    session.send(variables_request(locals, 0, 0)).await;
+    let variables = match session.response().await.body {
+        Some(ResponseBody::Variables(body)) => body.variables,
+        other => panic!("unexpected body: {:?}", other),
+    };
+    let a = variables
+        .iter()
+        .find(|v| v.name == "a")
+        .expect("a is a local");
+    // the value stays plain, the override shows in its type
+    assert_eq!(a.value, "10");
+    assert_eq!(a.type_field.as_deref(), Some("int (overridden)"));
+
+    // the program sees the new value, its assertion holds
+    session.send(continue_request()).await;
+    let _continued = session.response().await;
+    assert_eq!(session.exited().await, 0);
+
+    session.shutdown().await;
+}
@@ -0,0 +1,32 @@
+mod common;
+
+use common::request::set_function_breakpoints_request;
+use common::session::TestSession;
+
+use dawn_infra::dap_requests::ExtendedMessageKind;
+use debug_types::responses::ResponseBody;
+
+#[tokio::test]
+async fn test_set_function_breakpoints() {
+    let mut session = TestSession::new().await;
+
+    session.initialize().await;
+
+    let request = set_function_breakpoints_request(&["greet", "builtins.fetch*"]);
+    session.send(request).await;
+
+    let response = session.recv().await;
+    let breakpoints = match response.message {
+        ExtendedMessageKind::Response(r) if r.success => match r.body {
+            Some(ResponseBody::SetFunctionBreakpoints(body)) => body.breakpoints,
+            other => panic!("unexpected setFunctionBreakpoints body: {:?}", other),
+        },
+        other => panic!("bad setFunctionBreakpoints response: {:?}", other),
+    };
+
+    assert_eq!(breakpoints.len(), 2);
+    assert!(breakpoints.iter().all(|bp| bp.verified));
+    assert_ne!(breakpoints[0].id, breakpoints[1].id);
+
+    session.shutdown().await;
+}
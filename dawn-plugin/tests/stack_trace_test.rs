@@ -0,0 +1,35 @@
+mod common;
+
+use common::request::stack_trace_request;
+use common::session::TestSession;
+
+use dawn_infra::dap_requests::ExtendedMessageKind;
+use debug_types::responses::ResponseBody;
+
+#[tokio::test]
+async fn test_stack_trace_on_entry() {
+    let mut session = TestSession::new().await;
+
+    session
+        .start_on_entry("../tvix-debugger/tests/simple.nix")
+        .await;
+
+    session.send(stack_trace_request(0, 20)).await;
+    let response = session.recv().await;
+    let body = match response.message {
+        ExtendedMessageKind::Response(r) if r.success => match r.body {
+            Some(ResponseBody::StackTrace(body)) => body,
+            other => panic!("unexpected body: {:?}", other),
+        },
+        other => panic!("bad stackTrace response: {:?}", other),
+    };
+
+    // paused on the first op of the file, in its toplevel frame
+    let top = body.stack_frames.first().expect("a frame");
+    let source = top.source.as_ref().expect("the frame has a source");
+    assert!(source.path.as_ref().unwrap().ends_with("simple.nix"));
+    assert!(top.line >= 1);
+    assert_eq!(body.total_frames, Some(body.stack_frames.len() as i64));
+
+    session.shutdown().await;
+}
@@ -0,0 +1,91 @@
+mod common;
+
+use common::request::{
+    launch_request_with_file, next_request, set_breakpoints_request, step_in_request,
+    step_out_request,
+};
+use common::session::TestSession;
+
+use debug_types::events::StoppedEventReason;
+
+const PROGRAM: &str = "../tvix-debugger/tests/stepping.nix";
+
+/// Launches the program, stopped at the breakpoint on `a` of its body.
+async fn stop_at_body(session: &mut TestSession) {
+    session.initialize().await;
+    session.send(set_breakpoints_request(PROGRAM, &[7])).await;
+    let _breakpoints = session.response().await;
+    session
+        .launch(launch_request_with_file(PROGRAM, None))
+        .await;
+
+    let (reason, frame) = session.stopped().await;
+    assert_eq!(reason, StoppedEventReason::Breakpoint);
+    assert_eq!(frame.line, 7);
+}
+
+#[tokio::test]
+async fn test_next_steps_over_forced_thunks() {
+    let mut session = TestSession::new().await;
+    stop_at_body(&mut session).await;
+
+    // forcing `a` runs deeper, the step ends on the next line of the body
+    session.send(next_request(None)).await;
+    assert!(session.response().await.success);
+    let (reason, frame) = session.stopped().await;
+    assert_eq!(reason, StoppedEventReason::Step);
+    assert_eq!(frame.line, 8);
+
+    session.shutdown().await;
+}
+
+#[tokio::test]
+async fn test_step_in_and_out_of_calls() {
+    let mut session = TestSession::new().await;
+    stop_at_body(&mut session).await;
+
+    session.send(next_request(None)).await;
+    let _next = session.response().await;
+    let _stopped = session.stopped().await;
+
+    // into the thunk of `b`, then into the call of `double`
+    session.send(step_in_request()).await;
+    assert!(session.response().await.success);
+    let (reason, frame) = session.stopped().await;
+    assert_eq!(reason, StoppedEventReason::Step);
+    assert_eq!(frame.line, 5);
+
+    session.send(step_in_request()).await;
+    let _step_in = session.response().await;
+    let (_, frame) = session.stopped().await;
+    assert_eq!(frame.line, 3);
+    assert_eq!(frame.name, "double");
+
+    session.send(step_out_request()).await;
+    assert!(session.response().await.success);
+    let (reason, frame) = session.stopped().await;
+    assert_eq!(reason, StoppedEventReason::Step);
+    assert_ne!(frame.name, "double");
+
+    session.shutdown().await;
+}
+
+#[tokio::test]
+async fn test_next_granularity() {
+    let mut session = TestSession::new().await;
+    stop_at_body(&mut session).await;
+
+    // forcing `a` is another instruction of the same line
+    session.send(next_request(Some("instruction"))).await;
+    assert!(session.response().await.success);
+    let (reason, frame) = session.stopped().await;
+    assert_eq!(reason, StoppedEventReason::Step);
+    assert_eq!(frame.line, 7);
+
+    session.send(next_request(Some("line"))).await;
+    assert!(session.response().await.success);
+    let (_, frame) = session.stopped().await;
+    assert_eq!(frame.line, 8);
+
+    session.shutdown().await;
+}
@@ -0,0 +1,61 @@
+mod common;
+
+use common::request::{continue_request, launch_request_stop_on_entry, set_breakpoints_request};
+use common::session::TestSession;
+
+use debug_types::events::{EventBody, StoppedEventReason};
+use debug_types::responses::{Response, ResponseBody};
+
+const PROGRAM: &str = "../tvix-debugger/tests/simple.nix";
+
+/// Waits for the next stopped event, returns its reason, description and
+/// hit breakpoints.
+async fn stopped(session: &mut TestSession) -> (StoppedEventReason, String, Vec<i64>) {
+    match session
+        .event(|e| matches!(e, EventBody::Stopped { .. }))
+        .await
+    {
+        EventBody::Stopped {
+            reason,
+            description,
+            hit_breakpoint_ids,
+            ..
+        } => (
+            reason,
+            description.expect("a description"),
+            hit_breakpoint_ids.unwrap_or_default(),
+        ),
+        other => panic!("expected a stopped event: {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_stopped_events_say_why() {
+    let mut session = TestSession::new().await;
+
+    session.initialize().await;
+    session.send(set_breakpoints_request(PROGRAM, &[4])).await;
+    let id = match session.response().await {
+        Response {
+            success: true,
+            body: Some(ResponseBody::SetBreakpoints(body)),
+            ..
+        } => body.breakpoints[0].id.expect("a breakpoint id"),
+        other => panic!("bad setBreakpoints response: {:?}", other),
+    };
+    session.launch(launch_request_stop_on_entry(PROGRAM)).await;
+
+    let (reason, description, hit) = stopped(&mut session).await;
+    assert_eq!(reason, StoppedEventReason::Entry);
+    assert_eq!(description, "Paused on entry");
+    assert!(hit.is_empty());
+
+    session.send(continue_request()).await;
+    assert!(session.response().await.success);
+    let (reason, description, hit) = stopped(&mut session).await;
+    assert_eq!(reason, StoppedEventReason::Breakpoint);
+    assert_eq!(description, format!("Paused on breakpoint #{}", id));
+    assert_eq!(hit, vec![id]);
+
+    session.shutdown().await;
+}
@@ -0,0 +1,67 @@
+mod common;
+
+use common::request::{launch_request_stop_on_entry, launch_request_with_file, terminate_request};
+use common::session::TestSession;
+
+use dawn_infra::dap_requests::ExtendedMessageKind;
+use debug_types::events::EventBody;
+
+#[tokio::test]
+async fn test_finished_evaluation_exits() {
+    let mut session = TestSession::new().await;
+
+    session.initialize().await;
+
+    session
+        .launch(launch_request_with_file(
+            "../tvix-debugger/tests/simple.nix",
+            Some(".".into()),
+        ))
+        .await;
+
+    match session.recv().await.message {
+        ExtendedMessageKind::Event(e) => match e.body {
+            Some(EventBody::Exited { exit_code }) => assert_eq!(exit_code, 0),
+            other => panic!("unexpected event: {:?}", other),
+        },
+        other => panic!("expected an exited event: {:?}", other),
+    }
+    match session.recv().await.message {
+        ExtendedMessageKind::Event(e) => {
+            assert!(matches!(e.body, Some(EventBody::Terminated { .. })))
+        }
+        other => panic!("expected a terminated event: {:?}", other),
+    }
+
+    session.shutdown().await;
+}
+
+#[tokio::test]
+async fn test_terminate_keeps_session() {
+    let mut session = TestSession::new().await;
+
+    session
+        .start_on_entry("../tvix-debugger/tests/simple.nix")
+        .await;
+
+    session.send(terminate_request()).await;
+    match session.recv().await.message {
+        ExtendedMessageKind::Response(r) => assert!(r.success),
+        other => panic!("bad terminate response: {:?}", other),
+    }
+    match session.recv().await.message {
+        ExtendedMessageKind::Event(e) => {
+            assert!(matches!(e.body, Some(EventBody::Terminated { .. })))
+        }
+        other => panic!("expected a terminated event: {:?}", other),
+    }
+
+    // the debugger is still there for another launch
+    session
+        .launch(launch_request_stop_on_entry(
+            "../tvix-debugger/tests/simple.nix",
+        ))
+        .await;
+
+    session.shutdown().await;
+}
@@ -0,0 +1,55 @@
+mod common;
+
+use common::request::{scopes_request, stack_trace_request, variables_request};
+use common::session::TestSession;
+
+use dawn_infra::dap_requests::ExtendedMessageKind;
+use debug_types::responses::ResponseBody;
+
+#[tokio::test]
+async fn test_variables_page_builtins() {
+    let mut session = TestSession::new().await;
+
+    session
+        .start_on_entry("../tvix-debugger/tests/simple.nix")
+        .await;
+
+    session.send(stack_trace_request(0, 1)).await;
+    let frame_id = match session.recv().await.message {
+        ExtendedMessageKind::Response(r) => match r.body {
+            Some(ResponseBody::StackTrace(body)) => body.stack_frames[0].id,
+            other => panic!("unexpected body: {:?}", other),
+        },
+        other => panic!("bad stackTrace response: {:?}", other),
+    };
+
+    session.send(scopes_request(frame_id)).await;
+    let builtins = match session.recv().await.message {
+        ExtendedMessageKind::Response(r) => match r.body {
+            Some(ResponseBody::Scopes(body)) => body.scopes.last().cloned().unwrap(),
+            other => panic!("unexpected body: {:?}", other),
+        },
+        other => panic!("bad scopes response: {:?}", other),
+    };
+
+    session
+        .send(variables_request(builtins.variables_reference, 0, 3))
+        .await;
+    let variables = match session.recv().await.message {
+        ExtendedMessageKind::Response(r) if r.success => match r.body {
+            Some(ResponseBody::Variables(body)) => body.variables,
+            other => panic!("unexpected body: {:?}", other),
+        },
+        other => panic!("bad variables response: {:?}", other),
+    };
+    assert_eq!(variables.len(), 3);
+
+    // references that were never handed out are an error
+    session.send(variables_request(9999, 0, 0)).await;
+    match session.recv().await.message {
+        ExtendedMessageKind::Response(r) => assert!(!r.success),
+        other => panic!("expected a response: {:?}", other),
+    }
+
+    session.shutdown().await;
+}
@@ -1,16 +1,35 @@
-use std::path::PathBuf;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
 use std::thread::JoinHandle;
+use std::time::SystemTime;
 
-use debug_types::types::Capabilities;
+use debug_types::{
+    requests::{
+        BreakpointLocationsArguments, SetBreakpointsArguments, SetExceptionBreakpointsArguments,
+        SetFunctionBreakpointsArguments,
+    },
+    types::{
+        Breakpoint as DapBreakpoint, BreakpointLocation, Capabilities, FunctionBreakpoint,
+        SourceBreakpoint,
+    },
+};
 use tvix_eval::{EvalMode, Evaluation, SourceCode};
 
+use crate::breakpoints::{BreakpointEntry, HitCondition, SharedBreakpoints};
 use crate::commands::{
-    default_capabilities, Breakpoint, Command, CommandReply, ObserverCommand, ObserverReply,
+    default_capabilities, Breakpoint, Command, CommandReply, ObserverCommand, ObserverEvent,
+    ObserverReply,
 };
 use crate::config::Args;
+use crate::exceptions::{exception_breakpoint_filters, ExceptionFilters};
+use crate::locations::{canonicalize, CodeLocations};
 use crate::observer::DebugObserver;
 use crate::serde_smolstr::SerSmolStr;
+use crate::stack::page;
 
 struct ObserverClient {
     handle: JoinHandle<()>,
@@ -19,11 +38,24 @@ struct ObserverClient {
 }
 
 impl ObserverClient {
-    pub fn new(prog: SerSmolStr) -> Self {
+    pub fn new(
+        prog: SerSmolStr,
+        breakpoints: SharedBreakpoints,
+        interrupt: Arc<AtomicBool>,
+        paused: Arc<AtomicBool>,
+        events: Sender<ObserverEvent>,
+    ) -> Self {
         let (backend_sender, observer_reciever) = mpsc::channel::<ObserverCommand>();
         let (observer_sender, backend_reciever) = mpsc::channel::<ObserverReply>();
-        let handle =
-            ObserverClient::initialize_observer(prog.into(), observer_reciever, observer_sender);
+        let handle = ObserverClient::initialize_observer(
+            prog.into(),
+            breakpoints,
+            interrupt,
+            paused,
+            observer_reciever,
+            observer_sender,
+            events,
+        );
         ObserverClient {
             handle,
             receiver: backend_reciever,
@@ -33,21 +65,29 @@ impl ObserverClient {
 
     fn initialize_observer(
         program: PathBuf,
+        breakpoints: SharedBreakpoints,
+        interrupt: Arc<AtomicBool>,
+        paused: Arc<AtomicBool>,
         observer_reciever: Receiver<ObserverCommand>,
         observer_sender: Sender<ObserverReply>,
+        events: Sender<ObserverEvent>,
     ) -> JoinHandle<()> {
         std::thread::spawn(move || {
+            // the file is registered in the source map under this path, which
+            // is what file breakpoints are matched against
             let source_code = SourceCode::default();
-            let code_path = program.clone();
-            let file_name = code_path.file_name().unwrap().to_os_string();
+            let code_path = canonicalize(&program);
+            let report = events.clone();
 
-            source_code.add_file(
-                file_name.into_string().unwrap(),
-                program.to_str().unwrap().into(),
+            let mut observer = DebugObserver::new(
+                source_code.clone(),
+                breakpoints,
+                interrupt,
+                paused,
+                observer_reciever,
+                observer_sender,
+                events,
             );
-
-            let mut observer =
-                DebugObserver::new(source_code.clone(), observer_reciever, observer_sender);
             let eval = Evaluation::builder_impure()
                 .mode(EvalMode::Strict)
                 .with_source_map(source_code)
@@ -58,19 +98,16 @@ impl ObserverClient {
                 &code_path.to_str().unwrap()
             ));
             let result = eval.evaluate(code, Some(code_path));
-            println!("Execution done: {:?}", result);
-            observer.set_cmd(ObserverCommand::Done);
-
-            loop {
-                match observer.handle_command() {
-                    Ok(()) => continue,
-                    Err(e) => {
-                        println!("observer: ended with - {}", e.to_string());
-                        break;
-                    }
-                }
+            // the error the observer stopped the VM with is no news
+            if observer.is_terminated() {
+                let _ = report.send(ObserverEvent::Terminated);
+                return;
             }
-            println!("observer is done")
+            for error in &result.errors {
+                let _ = report.send(ObserverEvent::Output(error.to_string()));
+            }
+            let exit_code = if result.errors.is_empty() { 0 } else { 1 };
+            let _ = report.send(ObserverEvent::Exited(exit_code));
         })
     }
 }
@@ -83,6 +120,17 @@ pub struct TvixBackend {
     code_path: Option<PathBuf>,
     code: SourceCode,
     observer_client: Option<ObserverClient>,
+    breakpoints: SharedBreakpoints,
+    /// set to make the running program stop at the next op
+    interrupt: Arc<AtomicBool>,
+    /// set by the observer while the program is paused, only then does it
+    /// answer questions about the program
+    paused: Arc<AtomicBool>,
+    event_sender: Sender<ObserverEvent>,
+    /// events of the observer, until someone takes them with `events`
+    events: Option<Receiver<ObserverEvent>>,
+    /// compiled files, with the modification time they were compiled at
+    locations: HashMap<PathBuf, (SystemTime, Rc<CodeLocations>)>,
 }
 
 // FIXME: error handling if the observer_client isn't initialized
@@ -91,12 +139,19 @@ impl TvixBackend {
         let code = SourceCode::default();
         let code_path = None;
         let observer_client = None;
+        let (event_sender, events) = mpsc::channel::<ObserverEvent>();
 
         TvixBackend {
             state: DebuggerState::Uninitialized,
             code_path,
             code,
             observer_client,
+            breakpoints: SharedBreakpoints::default(),
+            interrupt: Arc::new(AtomicBool::new(false)),
+            paused: Arc::new(AtomicBool::new(false)),
+            event_sender,
+            events: Some(events),
+            locations: HashMap::new(),
         }
     }
 
@@ -104,39 +159,229 @@ impl TvixBackend {
         self.state
     }
 
+    /// Take the receiving end of the events the observer reports while the
+    /// program runs, only the first caller gets it
+    pub fn events(&mut self) -> Option<Receiver<ObserverEvent>> {
+        self.events.take()
+    }
+
     pub fn handle_command(&mut self, command: Command) -> CommandReply {
         match command {
             Command::Initialize => {
                 let capabilities = self.handle_initialize();
                 CommandReply::InitializeReply(capabilities)
             }
-            Command::Launch(prog) => {
-                self.handle_launch(prog);
+            Command::Launch {
+                program,
+                stop_on_entry,
+            } => {
+                self.handle_launch(program, stop_on_entry);
                 CommandReply::LaunchReply
             }
             Command::Step => {
                 self.handle_step();
                 CommandReply::StepReply
             }
-            Command::Break(breakpoint) => {
-                self.handle_break(breakpoint);
-                CommandReply::BreakReply
+            Command::Next(granularity) => {
+                self.send_observer(ObserverCommand::Next(granularity));
+                CommandReply::StepReply
+            }
+            Command::StepIn(granularity) => {
+                self.send_observer(ObserverCommand::StepIn(granularity));
+                CommandReply::StepReply
+            }
+            Command::StepOut => {
+                self.send_observer(ObserverCommand::StepOut);
+                CommandReply::StepReply
+            }
+            Command::Pause => {
+                // the observer only reads commands once paused, a flag left
+                // set without a running program would stop the next one
+                if self.observer_client.is_some() && !self.paused.load(Ordering::Relaxed) {
+                    self.interrupt.store(true, Ordering::Relaxed);
+                }
+                CommandReply::PauseReply
+            }
+            Command::Break {
+                location,
+                condition,
+                hit_condition,
+            } => {
+                let id = self.handle_break(location);
+                let condition = condition.map(|c| c.to_string());
+                let mut breakpoints = self.breakpoints.lock().unwrap();
+                breakpoints.set_condition(id, condition);
+                breakpoints.set_hit_condition(id, hit_condition);
+                CommandReply::BreakReply(id)
+            }
+            Command::Breakpoints => CommandReply::BreakpointsReply(self.list_breakpoints()),
+            Command::Delete(id) => {
+                self.breakpoints.lock().unwrap().remove(id);
+                CommandReply::BreakpointsReply(self.list_breakpoints())
+            }
+            Command::Enable(id) => {
+                self.breakpoints.lock().unwrap().set_enabled(id, true);
+                CommandReply::BreakpointsReply(self.list_breakpoints())
+            }
+            Command::Disable(id) => {
+                self.breakpoints.lock().unwrap().set_enabled(id, false);
+                CommandReply::BreakpointsReply(self.list_breakpoints())
+            }
+            Command::SetBreakpoints(args) => {
+                let breakpoints = self.handle_set_breakpoints(args);
+                CommandReply::SetBreakpointsReply(breakpoints)
+            }
+            Command::SetFunctionBreakpoints(args) => {
+                let breakpoints = self.handle_set_function_breakpoints(args);
+                CommandReply::SetFunctionBreakpointsReply(breakpoints)
+            }
+            Command::SetExceptionBreakpoints(args) => {
+                let breakpoints = self.handle_set_exception_breakpoints(args);
+                CommandReply::SetExceptionBreakpointsReply(breakpoints)
+            }
+            Command::BreakpointLocations(args) => {
+                let locations = self.handle_breakpoint_locations(args);
+                CommandReply::BreakpointLocationsReply(locations)
+            }
+            Command::StackTrace {
+                start_frame,
+                levels,
+            } => {
+                let frames = match self.query_observer(ObserverCommand::StackTrace) {
+                    Some(ObserverReply::StackTrace(frames)) => frames,
+                    _ => vec![],
+                };
+                let total = frames.len();
+                CommandReply::StackTraceReply {
+                    frames: page(frames, start_frame, levels),
+                    total,
+                }
+            }
+            Command::Scopes(frame_id) => {
+                let scopes = match self.query_observer(ObserverCommand::Scopes(frame_id)) {
+                    Some(ObserverReply::Scopes(scopes)) => scopes,
+                    _ => vec![],
+                };
+                CommandReply::ScopesReply(scopes)
+            }
+            Command::Variables {
+                reference,
+                start,
+                count,
+            } => {
+                let query = ObserverCommand::Variables {
+                    reference,
+                    start,
+                    count,
+                };
+                let variables = match self.query_observer(query) {
+                    Some(ObserverReply::Variables(variables)) => variables,
+                    _ => Err("the program is not paused".to_string()),
+                };
+                CommandReply::VariablesReply(variables)
+            }
+            Command::Evaluate {
+                expression,
+                frame_id,
+                context,
+            } => {
+                let query = ObserverCommand::Evaluate {
+                    expression,
+                    frame_id,
+                    context,
+                };
+                let evaluated = match self.query_observer(query) {
+                    Some(ObserverReply::Evaluated(evaluated)) => evaluated,
+                    _ => Err("the program is not paused".to_string()),
+                };
+                CommandReply::EvaluateReply(evaluated)
+            }
+            Command::Completions {
+                text,
+                cursor,
+                frame_id,
+            } => {
+                let query = ObserverCommand::Completions {
+                    text,
+                    cursor,
+                    frame_id,
+                };
+                let completions = match self.query_observer(query) {
+                    Some(ObserverReply::Completions(completions)) => completions,
+                    _ => vec![],
+                };
+                CommandReply::CompletionsReply(completions)
+            }
+            Command::ExceptionInfo => {
+                let info = match self.query_observer(ObserverCommand::ExceptionInfo) {
+                    Some(ObserverReply::ExceptionInfo(Some(info))) => Ok(info),
+                    Some(ObserverReply::ExceptionInfo(None)) => {
+                        Err("the program is not stopped on an exception".to_string())
+                    }
+                    _ => Err("the program is not paused".to_string()),
+                };
+                CommandReply::ExceptionInfoReply(info)
+            }
+            Command::SetVariable {
+                reference,
+                name,
+                value,
+            } => {
+                let query = ObserverCommand::SetVariable {
+                    reference,
+                    name,
+                    value,
+                };
+                let overridden = match self.query_observer(query) {
+                    Some(ObserverReply::Overridden(overridden)) => overridden,
+                    _ => Err("the program is not paused".to_string()),
+                };
+                CommandReply::SetVariableReply(overridden)
+            }
+            Command::SetExpression {
+                expression,
+                value,
+                frame_id,
+            } => {
+                let query = ObserverCommand::SetExpression {
+                    expression,
+                    value,
+                    frame_id,
+                };
+                let overridden = match self.query_observer(query) {
+                    Some(ObserverReply::Overridden(overridden)) => overridden,
+                    _ => Err("the program is not paused".to_string()),
+                };
+                CommandReply::SetExpressionReply(overridden)
             }
             Command::Print(var_name) => {
-                self.handle_print(var_name);
-                CommandReply::PrintReply
+                let printed = match self.query_observer(ObserverCommand::Print(var_name)) {
+                    Some(ObserverReply::Printed(printed)) => printed,
+                    _ => Err("the program is not paused".to_string()),
+                };
+                CommandReply::PrintReply(printed)
             }
             Command::Continue => {
                 self.handle_continue();
                 CommandReply::LaunchReply
             }
             Command::Exit => {
-                println!("backend: got an exit, exting");
-                self.handle_exit();
-                (*self).exit();
-                println!("backend: exited");
+                self.exit();
                 CommandReply::ExitReply
             }
+            Command::Terminate => {
+                self.handle_terminate();
+                CommandReply::TerminateReply
+            }
+            Command::Disconnect { terminate_debuggee } => {
+                if terminate_debuggee {
+                    self.handle_terminate();
+                } else {
+                    self.handle_detach();
+                }
+                self.state = DebuggerState::ShutDown;
+                CommandReply::DisconnectReply
+            }
             _ => {
                 unreachable!("Unknown command in backend: {}", command)
             }
@@ -146,73 +391,427 @@ impl TvixBackend {
     fn handle_initialize(&mut self) -> Capabilities {
         Capabilities {
             supports_configuration_done_request: Some(true),
+            supports_stepping_granularity: Some(true),
+            supports_function_breakpoints: Some(true),
+            supports_breakpoint_locations_request: Some(true),
+            supports_conditional_breakpoints: Some(true),
+            supports_hit_conditional_breakpoints: Some(true),
+            supports_log_points: Some(true),
+            supports_evaluate_for_hovers: Some(true),
+            supports_completions_request: Some(true),
+            completion_trigger_characters: Some(vec![".".to_string()]),
+            supports_exception_info_request: Some(true),
+            supports_set_variable: Some(true),
+            supports_set_expression: Some(true),
+            supports_terminate_request: Some(true),
+            support_terminate_debuggee: Some(true),
+            exception_breakpoint_filters: Some(exception_breakpoint_filters()),
             ..default_capabilities()
         }
     }
 
-    fn handle_exit(&mut self) {
-        let _ = self
-            .observer_client
-            .as_mut()
-            .unwrap()
-            .sender
-            .send(ObserverCommand::Exit);
-        let _ = self.observer_client.as_mut().unwrap().receiver.recv();
+    /// Stop the program, if one runs, and wait for its thread to end
+    fn handle_terminate(&mut self) {
+        self.end_observer(ObserverCommand::Exit);
     }
 
-    fn handle_launch(&mut self, prog: SerSmolStr) {
-        self.observer_client = Some(ObserverClient::new(prog.clone()));
+    /// Let the program, if one runs, run to its end without stopping
+    fn handle_detach(&mut self) {
+        self.end_observer(ObserverCommand::Detach);
+    }
+
+    /// Hand the observer a command ending the session. A running program
+    /// only looks for it when interrupted. The thread of a terminated
+    /// program is joined, it ends at the next op. A detached one is left to
+    /// run to its end with nobody waiting for it, which is safe: the thread
+    /// owns the evaluation and the observer, never blocks on the dropped
+    /// channels, and ends with the adapter at the latest
+    fn end_observer(&mut self, command: ObserverCommand) {
+        let Some(observer_client) = self.observer_client.take() else {
+            return;
+        };
+        let detach = command == ObserverCommand::Detach;
+        let _ = observer_client.sender.send(command);
+        self.interrupt.store(true, Ordering::Relaxed);
+        if !detach {
+            let _ = observer_client.handle.join();
+            self.interrupt.store(false, Ordering::Relaxed);
+        }
+        self.paused.store(false, Ordering::Relaxed);
+    }
+
+    fn handle_launch(&mut self, prog: SerSmolStr, stop_on_entry: bool) {
+        // a program launched before is ended, its thread is joined
+        self.end_observer(ObserverCommand::Exit);
+        let code_path = canonicalize(Path::new(&*prog));
+        // lines given in the repl before there was a program are its lines
+        self.breakpoints
+            .lock()
+            .unwrap()
+            .bind_lines(&code_path.to_string_lossy());
+        self.code_path = Some(code_path);
+        self.interrupt.store(false, Ordering::Relaxed);
+        self.observer_client = Some(ObserverClient::new(
+            prog.clone(),
+            self.breakpoints.clone(),
+            self.interrupt.clone(),
+            self.paused.clone(),
+            self.event_sender.clone(),
+        ));
         let _ = self
             .observer_client
             .as_mut()
             .unwrap()
             .sender
-            .send(ObserverCommand::Launch(prog));
+            .send(ObserverCommand::Launch {
+                program: prog,
+                stop_on_entry,
+            });
         // let state = self.receiver.recv();
     }
 
     fn handle_continue(&mut self) {
-        let _ = self
-            .observer_client
-            .as_mut()
-            .unwrap()
-            .sender
-            .send(ObserverCommand::Continue);
+        self.send_observer(ObserverCommand::Continue);
     }
 
     fn handle_step(&mut self) {
-        let _ = self
-            .observer_client
-            .as_mut()
+        self.send_observer(ObserverCommand::Step);
+    }
+
+    /// Forward a command to the observer, dropped if nothing was launched
+    fn send_observer(&mut self, command: ObserverCommand) {
+        if let Some(observer_client) = &self.observer_client {
+            let _ = observer_client.sender.send(command);
+        }
+    }
+
+    /// Ask the observer about the program and wait for the answer, `None`
+    /// unless it is paused: a running program doesn't read commands
+    fn query_observer(&mut self, command: ObserverCommand) -> Option<ObserverReply> {
+        let observer_client = self.observer_client.as_ref()?;
+        if !self.paused.load(Ordering::Relaxed) {
+            return None;
+        }
+        observer_client.sender.send(command).ok()?;
+        match observer_client.receiver.recv().ok()? {
+            ObserverReply::Done => None,
+            reply => Some(reply),
+        }
+    }
+
+    fn handle_break(&mut self, breakpoint: Breakpoint) -> usize {
+        // a breakpoint without a file refers to the launched program
+        let breakpoint = match (breakpoint, &self.code_path) {
+            (Breakpoint::Line(line), Some(program)) => Breakpoint::FileLine {
+                file: program.to_string_lossy().as_ref().into(),
+                line,
+                column: None,
+            },
+            (Breakpoint::FileLine { file, line, column }, _) => Breakpoint::FileLine {
+                file: canonicalize(Path::new(&*file))
+                    .to_string_lossy()
+                    .as_ref()
+                    .into(),
+                line,
+                column,
+            },
+            (breakpoint, _) => breakpoint,
+        };
+        self.breakpoints.lock().unwrap().add(breakpoint)
+    }
+
+    fn list_breakpoints(&self) -> Vec<BreakpointEntry> {
+        self.breakpoints
+            .lock()
             .unwrap()
-            .sender
-            .send(ObserverCommand::Step);
+            .entries()
+            .cloned()
+            .collect()
     }
 
-    fn handle_break(&mut self, breakpoint: Breakpoint) {
-        println!("got breakpoint: {:?}", &breakpoint);
-        let _ = self
-            .observer_client
-            .as_mut()
+    /// Replace the breakpoints of a file with the requested ones, each
+    /// verified against the compiled file and moved to the code it hits
+    fn handle_set_breakpoints(&mut self, args: SetBreakpointsArguments) -> Vec<DapBreakpoint> {
+        let requested: Vec<(i64, Option<i64>)> = match (&args.breakpoints, &args.lines) {
+            (Some(breakpoints), _) => breakpoints.iter().map(|bp| (bp.line, bp.column)).collect(),
+            (None, Some(lines)) => lines.iter().map(|line| (*line, None)).collect(),
+            (None, None) => vec![],
+        };
+        let settings: Vec<Option<SourceBreakpoint>> = match &args.breakpoints {
+            Some(breakpoints) => breakpoints.iter().cloned().map(Some).collect(),
+            None => vec![None; requested.len()],
+        };
+
+        let Some(path) = args.source.path.clone() else {
+            return requested
+                .into_iter()
+                .map(|(line, _)| unverified_breakpoint(&args, line, "source has no path"))
+                .collect();
+        };
+
+        let locations = self.code_locations(Path::new(&path));
+        let checked: Vec<Result<(usize, Option<usize>), String>> = requested
+            .iter()
+            .zip(&settings)
+            .map(|(&(line, column), settings)| {
+                let hit_condition = settings.as_ref().and_then(|bp| bp.hit_condition.as_ref());
+                if let Some(hit_condition) = hit_condition {
+                    hit_condition.parse::<HitCondition>()?;
+                }
+                let line = usize::try_from(line)
+                    .map_err(|_| format!("no code on or after line {}", line))?;
+                Ok((line, column.and_then(|c| usize::try_from(c).ok())))
+            })
+            .collect();
+
+        // breakpoints on no code are kept, the file may change before the
+        // program loads it
+        let positions: Vec<(usize, Option<usize>)> = checked.iter().flatten().copied().collect();
+        let file = canonicalize(Path::new(&path));
+        let mut ids = self
+            .breakpoints
+            .lock()
             .unwrap()
-            .sender
-            .send(ObserverCommand::Break(breakpoint));
+            .replace_file(&file.to_string_lossy(), &positions)
+            .into_iter();
+
+        requested
+            .into_iter()
+            .zip(checked)
+            .zip(settings)
+            .map(|(((line, _), checked), settings)| {
+                let (line, column) = match checked {
+                    Ok(position) => position,
+                    Err(e) => return unverified_breakpoint(&args, line, &e),
+                };
+                let Some(id) = ids.next() else {
+                    return unverified_breakpoint(&args, line as i64, "breakpoint was not set");
+                };
+                let resolved = match &locations {
+                    Ok(locations) => locations.resolve(line, column),
+                    Err(e) => Err(e.clone()),
+                };
+                self.breakpoints.lock().unwrap().relocate(id, resolved);
+                self.configure_breakpoint(
+                    id,
+                    settings.as_ref().and_then(|bp| bp.condition.clone()),
+                    settings.as_ref().and_then(|bp| bp.hit_condition.as_deref()),
+                    settings.as_ref().and_then(|bp| bp.log_message.clone()),
+                );
+                let entry = self.breakpoints.lock().unwrap().get(id).cloned();
+                match entry {
+                    Some(entry) => DapBreakpoint {
+                        source: Some(args.source.clone()),
+                        ..dap_breakpoint(&entry)
+                    },
+                    None => unverified_breakpoint(&args, line as i64, "breakpoint was not set"),
+                }
+            })
+            .collect()
     }
 
-    fn handle_print(&mut self, var_name: SerSmolStr) {
-        let _ = self
-            .observer_client
-            .as_mut()
+    /// Replace the function breakpoints, they can't be checked against the
+    /// code before the function is called, only their hit condition is
+    fn handle_set_function_breakpoints(
+        &mut self,
+        args: SetFunctionBreakpointsArguments,
+    ) -> Vec<DapBreakpoint> {
+        let checked: Vec<Result<&FunctionBreakpoint, String>> = args
+            .breakpoints
+            .iter()
+            .map(|bp| match &bp.hit_condition {
+                Some(hit_condition) => hit_condition.parse::<HitCondition>().map(|_| bp),
+                None => Ok(bp),
+            })
+            .collect();
+
+        let patterns: Vec<&str> = checked
+            .iter()
+            .flatten()
+            .map(|bp| bp.name.as_str())
+            .collect();
+        let mut ids = self
+            .breakpoints
+            .lock()
             .unwrap()
-            .sender
-            .send(ObserverCommand::Print(var_name));
+            .replace_functions(&patterns)
+            .into_iter();
+
+        checked
+            .into_iter()
+            .map(|checked| {
+                let (id, verified, message) = match checked {
+                    Ok(bp) => {
+                        let id = ids.next();
+                        if let Some(id) = id {
+                            self.configure_breakpoint(
+                                id,
+                                bp.condition.clone(),
+                                bp.hit_condition.as_deref(),
+                                None,
+                            );
+                        }
+                        (id.map(|id| id as i64), true, None)
+                    }
+                    Err(e) => (None, false, Some(e)),
+                };
+                DapBreakpoint {
+                    id,
+                    verified,
+                    message,
+                    source: None,
+                    line: None,
+                    column: None,
+                    end_line: None,
+                    end_column: None,
+                    instruction_reference: None,
+                    offset: None,
+                }
+            })
+            .collect()
+    }
+
+    /// Replace the exception filters, one breakpoint per requested filter
+    fn handle_set_exception_breakpoints(
+        &mut self,
+        args: SetExceptionBreakpointsArguments,
+    ) -> Vec<DapBreakpoint> {
+        let (filters, unknown) =
+            ExceptionFilters::from_ids(args.filters.iter().map(String::as_str));
+        self.breakpoints
+            .lock()
+            .unwrap()
+            .set_exception_filters(filters);
+
+        args.filters
+            .iter()
+            .map(|filter| {
+                let known = !unknown.contains(&filter.as_str());
+                DapBreakpoint {
+                    id: None,
+                    verified: known,
+                    message: (!known).then(|| format!("unknown exception filter {}", filter)),
+                    source: None,
+                    line: None,
+                    column: None,
+                    end_line: None,
+                    end_column: None,
+                    instruction_reference: None,
+                    offset: None,
+                }
+            })
+            .collect()
+    }
+
+    /// Set what a breakpoint does once reached, the hit condition has to be
+    /// validated beforehand
+    fn configure_breakpoint(
+        &self,
+        id: usize,
+        condition: Option<String>,
+        hit_condition: Option<&str>,
+        log_message: Option<String>,
+    ) {
+        let hit_condition = hit_condition.and_then(|c| c.parse::<HitCondition>().ok());
+        let mut breakpoints = self.breakpoints.lock().unwrap();
+        breakpoints.set_condition(id, condition);
+        breakpoints.set_hit_condition(id, hit_condition);
+        breakpoints.set_log_message(id, log_message);
+    }
+
+    /// All distinct ranges in the requested lines of a source where
+    /// execution can stop
+    fn handle_breakpoint_locations(
+        &mut self,
+        args: BreakpointLocationsArguments,
+    ) -> Vec<BreakpointLocation> {
+        let Some(path) = args.source.path.as_ref() else {
+            return vec![];
+        };
+        let Ok(locations) = self.code_locations(Path::new(path)) else {
+            return vec![];
+        };
+
+        let first = (args.line, args.column.unwrap_or(1));
+        let last = (
+            args.end_line.unwrap_or(args.line),
+            args.end_column.unwrap_or(i64::MAX),
+        );
+        locations
+            .ranges()
+            .into_iter()
+            .map(
+                |((line, column), (end_line, end_column))| BreakpointLocation {
+                    line: line as i64,
+                    column: Some(column as i64),
+                    end_line: Some(end_line as i64),
+                    end_column: Some(end_column as i64),
+                },
+            )
+            .filter(|location| {
+                let start = (location.line, location.column.unwrap_or(1));
+                first <= start && start <= last
+            })
+            .collect()
+    }
+
+    /// The compiled locations of a file, which is only recompiled when it
+    /// changed on disk
+    fn code_locations(&mut self, path: &Path) -> Result<Rc<CodeLocations>, String> {
+        let modified = std::fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .map_err(|e| format!("could not read {}: {}", path.display(), e))?;
+
+        if let Some((compiled_at, locations)) = self.locations.get(path) {
+            if *compiled_at == modified {
+                return Ok(locations.clone());
+            }
+        }
+
+        let locations = Rc::new(CodeLocations::compile(path)?);
+        self.locations
+            .insert(path.to_path_buf(), (modified, locations.clone()));
+        Ok(locations)
     }
 
     pub fn exit(&mut self) {
-        // TODO: send exit to the evaluator, join the handle, return
-        println!("got exit, joining observer");
-        let _ = self.observer_client.take().unwrap().handle.join();
-        println!("observer joined");
+        self.handle_terminate();
+    }
+}
+
+/// A breakpoint of the store as DAP reports it, without its source
+pub fn dap_breakpoint(entry: &BreakpointEntry) -> DapBreakpoint {
+    let (line, column) = match &entry.location {
+        Breakpoint::FileLine { line, column, .. } => (Some(*line as i64), column.map(|c| c as i64)),
+        Breakpoint::Line(line) => (Some(*line as i64), None),
+        Breakpoint::Function(_) => (None, None),
+    };
+    DapBreakpoint {
+        id: Some(entry.id as i64),
+        verified: entry.verified,
+        message: entry.message.clone(),
+        source: None,
+        line,
+        column,
+        end_line: None,
+        end_column: None,
+        instruction_reference: None,
+        offset: None,
+    }
+}
+
+fn unverified_breakpoint(args: &SetBreakpointsArguments, line: i64, msg: &str) -> DapBreakpoint {
+    DapBreakpoint {
+        id: None,
+        verified: false,
+        message: Some(msg.to_string()),
+        source: Some(args.source.clone()),
+        line: Some(line),
+        column: None,
+        end_line: None,
+        end_column: None,
+        instruction_reference: None,
+        offset: None,
     }
 }
 
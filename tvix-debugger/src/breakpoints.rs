@@ -0,0 +1,675 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Display,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{commands::Breakpoint, exceptions::ExceptionFilters, locations::Location};
+
+/// Breakpoints shared between the backend (which edits them) and the
+/// observer (which hits them)
+pub type SharedBreakpoints = Arc<Mutex<BreakpointStore>>;
+
+/// When a breakpoint stops, depending on how many times it was hit
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HitCondition {
+    Equal(usize),
+    Greater(usize),
+    GreaterOrEqual(usize),
+    Less(usize),
+    LessOrEqual(usize),
+    /// every n-th hit
+    Multiple(usize),
+}
+
+impl HitCondition {
+    pub fn is_met(&self, hits: usize) -> bool {
+        match *self {
+            HitCondition::Equal(n) => hits == n,
+            HitCondition::Greater(n) => hits > n,
+            HitCondition::GreaterOrEqual(n) => hits >= n,
+            HitCondition::Less(n) => hits < n,
+            HitCondition::LessOrEqual(n) => hits <= n,
+            HitCondition::Multiple(n) => hits % n == 0,
+        }
+    }
+}
+
+impl Display for HitCondition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HitCondition::Equal(n) => write!(f, "hit == {}", n),
+            HitCondition::Greater(n) => write!(f, "hit > {}", n),
+            HitCondition::GreaterOrEqual(n) => write!(f, "hit >= {}", n),
+            HitCondition::Less(n) => write!(f, "hit < {}", n),
+            HitCondition::LessOrEqual(n) => write!(f, "hit <= {}", n),
+            HitCondition::Multiple(n) => write!(f, "hit % {} == 0", n),
+        }
+    }
+}
+
+impl FromStr for HitCondition {
+    type Err = String;
+
+    /// Parses `5`, `== 5`, `>= 10`, `% 100 == 0`..., optionally prefixed with
+    /// `hit` like in the repl, a bare number means `== n`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid hit condition `{}`", s);
+        let condition = s.trim();
+        let condition = condition.strip_prefix("hit").unwrap_or(condition).trim();
+        let number = |n: &str| n.trim().parse::<usize>().map_err(|_| invalid());
+
+        if let Some(rest) = condition.strip_prefix('%') {
+            let n = match rest.split_once("==") {
+                Some((n, zero)) if zero.trim() == "0" => n,
+                Some(_) => return Err(invalid()),
+                None => rest,
+            };
+            // every 0th hit never comes
+            return match number(n)? {
+                0 => Err(invalid()),
+                n => Ok(HitCondition::Multiple(n)),
+            };
+        }
+
+        // longest operators first, `>=` starts with `>`
+        let operators: [(&str, fn(usize) -> HitCondition); 6] = [
+            ("==", HitCondition::Equal),
+            (">=", HitCondition::GreaterOrEqual),
+            ("<=", HitCondition::LessOrEqual),
+            (">", HitCondition::Greater),
+            ("<", HitCondition::Less),
+            ("=", HitCondition::Equal),
+        ];
+        for (operator, condition_of) in operators {
+            if let Some(n) = condition.strip_prefix(operator) {
+                return number(n).map(condition_of);
+            }
+        }
+        number(condition).map(HitCondition::Equal)
+    }
+}
+
+/// A breakpoint that stays set until it is removed
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BreakpointEntry {
+    /// stable id, also used as the DAP breakpoint id
+    pub id: usize,
+    pub location: Breakpoint,
+    /// nix expression that has to evaluate to true for the breakpoint to stop
+    pub condition: Option<String>,
+    /// only stop on the hits matching this, counting the hits where the
+    /// condition held
+    pub hit_condition: Option<HitCondition>,
+    /// print this instead of stopping, with `{expr}` replaced by the value
+    /// of the nix expression
+    pub log_message: Option<String>,
+    pub enabled: bool,
+    /// how many times execution reached this breakpoint
+    pub hits: usize,
+    /// where a breakpoint of the DAP client was requested, `location` is
+    /// where it was moved to: the first code on or after that position
+    pub requested: Option<(usize, Option<usize>)>,
+    /// unverified breakpoints are on no code and never hit
+    pub verified: bool,
+    /// why the breakpoint is unverified or was moved
+    pub message: Option<String>,
+}
+
+impl Display for BreakpointEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let state = match (self.verified, self.enabled) {
+            (false, _) => "unverified",
+            (true, true) => "enabled",
+            (true, false) => "disabled",
+        };
+        write!(f, "#{} {}", self.id, self.location)?;
+        if let Some(condition) = &self.condition {
+            write!(f, " if {}", condition)?;
+        }
+        if let Some(hit_condition) = &self.hit_condition {
+            let separator = if self.condition.is_some() {
+                "and"
+            } else {
+                "if"
+            };
+            write!(f, " {} {}", separator, hit_condition)?;
+        }
+        if let Some(log_message) = &self.log_message {
+            write!(f, " log {:?}", log_message)?;
+        }
+        write!(f, " ({}, {} hits)", state, self.hits)
+    }
+}
+
+/// All breakpoints of a debugging session, used by both the repl and the
+/// DAP adapter
+#[derive(Debug, Default)]
+pub struct BreakpointStore {
+    last_id: usize,
+    entries: BTreeMap<usize, BreakpointEntry>,
+    /// the exceptions that stop execution
+    exceptions: ExceptionFilters,
+    /// set when the exception filters change, the observer keeps a copy of
+    /// them rather than lock the store on every op
+    exceptions_changed: Arc<AtomicBool>,
+    /// bumped when a breakpoint changes, the observer keeps a snapshot of
+    /// them rather than lock the store on every op. Hits don't count
+    generation: Arc<AtomicUsize>,
+}
+
+impl BreakpointStore {
+    /// Add a new enabled breakpoint, returning its id
+    pub fn add(&mut self, location: Breakpoint) -> usize {
+        self.last_id += 1;
+        let entry = BreakpointEntry {
+            id: self.last_id,
+            location,
+            condition: None,
+            hit_condition: None,
+            log_message: None,
+            enabled: true,
+            hits: 0,
+            requested: None,
+            verified: true,
+            message: None,
+        };
+        self.entries.insert(entry.id, entry);
+        self.changed();
+        self.last_id
+    }
+
+    pub fn remove(&mut self, id: usize) -> Option<BreakpointEntry> {
+        self.changed();
+        self.entries.remove(&id)
+    }
+
+    /// Enable or disable a breakpoint, returns false if it doesn't exist
+    pub fn set_enabled(&mut self, id: usize, enabled: bool) -> bool {
+        match self.entries.get_mut(&id) {
+            Some(entry) => {
+                entry.enabled = enabled;
+                self.changed();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Set the condition of a breakpoint, returns false if it doesn't exist
+    pub fn set_condition(&mut self, id: usize, condition: Option<String>) -> bool {
+        match self.entries.get_mut(&id) {
+            Some(entry) => {
+                entry.condition = condition;
+                self.changed();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Set the hit condition of a breakpoint, returns false if it doesn't exist
+    pub fn set_hit_condition(&mut self, id: usize, hit_condition: Option<HitCondition>) -> bool {
+        match self.entries.get_mut(&id) {
+            Some(entry) => {
+                entry.hit_condition = hit_condition;
+                self.changed();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Turn a breakpoint into a logpoint, or back with `None`. Returns false
+    /// if it doesn't exist
+    pub fn set_log_message(&mut self, id: usize, log_message: Option<String>) -> bool {
+        match self.entries.get_mut(&id) {
+            Some(entry) => {
+                entry.log_message = log_message;
+                self.changed();
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn get(&self, id: usize) -> Option<&BreakpointEntry> {
+        self.entries.get(&id)
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = &BreakpointEntry> {
+        self.entries.values()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn exception_filters(&self) -> &ExceptionFilters {
+        &self.exceptions
+    }
+
+    pub fn set_exception_filters(&mut self, exceptions: ExceptionFilters) {
+        self.exceptions = exceptions;
+        self.exceptions_changed.store(true, Ordering::Relaxed);
+    }
+
+    /// The flag set whenever the exception filters change
+    pub fn exceptions_changed(&self) -> Arc<AtomicBool> {
+        self.exceptions_changed.clone()
+    }
+
+    /// The counter bumped whenever a breakpoint changes
+    pub fn generation(&self) -> Arc<AtomicUsize> {
+        self.generation.clone()
+    }
+
+    fn changed(&self) {
+        self.generation.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// A copy of the breakpoints to match against without holding the lock,
+    /// their hits are still recorded in the store
+    pub fn snapshot(&self) -> BreakpointStore {
+        BreakpointStore {
+            last_id: self.last_id,
+            entries: self.entries.clone(),
+            ..BreakpointStore::default()
+        }
+    }
+
+    /// Replace all the breakpoints of `file` with breakpoints requested on
+    /// `positions` (a line and optional column). Breakpoints requested on
+    /// positions that were already set keep their id, returns the id for
+    /// each of the positions. New breakpoints are unverified until
+    /// `relocate`d
+    pub fn replace_file(&mut self, file: &str, positions: &[(usize, Option<usize>)]) -> Vec<usize> {
+        let mut kept: HashMap<(usize, Option<usize>), usize> = HashMap::new();
+        self.changed();
+        self.entries
+            .retain(|id, entry| match (&entry.location, entry.requested) {
+                (Breakpoint::FileLine { file: f, .. }, Some(requested)) if **f == *file => {
+                    if positions.contains(&requested) {
+                        kept.insert(requested, *id);
+                        true
+                    } else {
+                        false
+                    }
+                }
+                _ => true,
+            });
+
+        positions
+            .iter()
+            .map(|&(line, column)| match kept.get(&(line, column)) {
+                Some(id) => *id,
+                None => {
+                    let id = self.add(Breakpoint::FileLine {
+                        file: file.into(),
+                        line,
+                        column,
+                    });
+                    if let Some(entry) = self.entries.get_mut(&id) {
+                        entry.requested = Some((line, column));
+                        entry.verified = false;
+                    }
+                    kept.insert((line, column), id);
+                    id
+                }
+            })
+            .collect()
+    }
+
+    /// Put the breakpoints set on a line of no particular file, before a
+    /// program was launched, into `file`, the launched program
+    pub fn bind_lines(&mut self, file: &str) {
+        self.changed();
+        for entry in self.entries.values_mut() {
+            if let Breakpoint::Line(line) = entry.location {
+                entry.location = Breakpoint::FileLine {
+                    file: file.into(),
+                    line,
+                    column: None,
+                };
+            }
+        }
+    }
+
+    /// The ids of the breakpoints the DAP client requested in `file`
+    pub fn file_breakpoints(&self, file: &str) -> Vec<usize> {
+        self.entries
+            .values()
+            .filter(|entry| {
+                entry.requested.is_some()
+                    && matches!(&entry.location, Breakpoint::FileLine { file: f, .. } if **f == *file)
+            })
+            .map(|entry| entry.id)
+            .collect()
+    }
+
+    /// Move the requested breakpoint `id` to `resolved`, the position the
+    /// code of its file puts it at, or mark it unverified with the reason
+    /// there is none. Returns whether that changed the breakpoint
+    pub fn relocate(
+        &mut self,
+        id: usize,
+        resolved: Result<(usize, Option<usize>), String>,
+    ) -> bool {
+        let Some(entry) = self.entries.get_mut(&id) else {
+            return false;
+        };
+        let (Some((line, column)), Breakpoint::FileLine { file, .. }) =
+            (entry.requested, &entry.location)
+        else {
+            return false;
+        };
+        let file = file.clone();
+
+        let (location, verified, message) = match resolved {
+            Ok((resolved_line, resolved_column)) => {
+                let message = if resolved_line != line {
+                    Some(format!(
+                        "no code on line {}, moved to line {}",
+                        line, resolved_line
+                    ))
+                } else {
+                    match (column, resolved_column) {
+                        (Some(column), None) => Some(format!(
+                            "no expression at column {}, breaking on the line",
+                            column
+                        )),
+                        (Some(_), resolved) if resolved != column => Some(
+                            "no expression starts at this column, moved to the next one".into(),
+                        ),
+                        _ => None,
+                    }
+                };
+                let location = Breakpoint::FileLine {
+                    file,
+                    line: resolved_line,
+                    column: resolved_column,
+                };
+                (location, true, message)
+            }
+            Err(e) => (Breakpoint::FileLine { file, line, column }, false, Some(e)),
+        };
+
+        let changed =
+            entry.location != location || entry.verified != verified || entry.message != message;
+        entry.location = location;
+        entry.verified = verified;
+        entry.message = message;
+        if changed {
+            self.changed();
+        }
+        changed
+    }
+
+    /// Replace all the function breakpoints with breakpoints on `patterns`,
+    /// keeping the ids of the patterns that were already set. Returns the id
+    /// for each of the patterns
+    pub fn replace_functions(&mut self, patterns: &[&str]) -> Vec<usize> {
+        let mut kept: HashMap<String, usize> = HashMap::new();
+        self.changed();
+        self.entries.retain(|id, entry| match &entry.location {
+            Breakpoint::Function(pattern) => {
+                if patterns.contains(&&**pattern) {
+                    kept.insert(pattern.to_string(), *id);
+                    true
+                } else {
+                    false
+                }
+            }
+            _ => true,
+        });
+
+        patterns
+            .iter()
+            .map(|&pattern| match kept.get(pattern) {
+                Some(id) => *id,
+                None => {
+                    let id = self.add(Breakpoint::Function(pattern.into()));
+                    kept.insert(pattern.to_string(), id);
+                    id
+                }
+            })
+            .collect()
+    }
+
+    /// The enabled function breakpoints reached by entering `name`
+    pub fn reached_function(&self, name: &str) -> Vec<BreakpointEntry> {
+        self.entries
+            .values()
+            .filter(|entry| entry.enabled && entry.location.matches_function(name))
+            .cloned()
+            .collect()
+    }
+
+    /// The enabled breakpoints reached by an op executed at `location`. The
+    /// file of `location` has to be canonical, like the paths breakpoints are
+    /// stored under
+    pub fn reached(&self, location: &Location, entered_line: bool) -> Vec<BreakpointEntry> {
+        self.entries
+            .values()
+            .filter(|entry| {
+                entry.enabled && entry.verified && entry.location.matches(location, entered_line)
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// Count a hit of a breakpoint whose condition held, returns whether the
+    /// breakpoint should stop according to its hit condition
+    pub fn record_hit(&mut self, id: usize) -> bool {
+        let Some(entry) = self.entries.get_mut(&id) else {
+            return false;
+        };
+        entry.hits += 1;
+        entry
+            .hit_condition
+            .is_none_or(|hit_condition| hit_condition.is_met(entry.hits))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+
+    fn entry_ids(entries: Vec<BreakpointEntry>) -> Vec<usize> {
+        entries.into_iter().map(|entry| entry.id).collect()
+    }
+
+    fn location(file: &str, line: usize, column: usize) -> Location {
+        Location {
+            file: Path::new(file).into(),
+            line,
+            column,
+        }
+    }
+
+    #[test]
+    fn breakpoints_persist_across_hits() {
+        let mut store = BreakpointStore::default();
+        let id = store.add(Breakpoint::Line(3));
+        let loc = location("/default.nix", 3, 1);
+
+        assert_eq!(entry_ids(store.reached(&loc, true)), vec![id]);
+        store.record_hit(id);
+        assert_eq!(entry_ids(store.reached(&loc, true)), vec![id]);
+        store.record_hit(id);
+        assert_eq!(store.get(id).unwrap().hits, 2);
+
+        store.set_enabled(id, false);
+        assert!(store.reached(&loc, true).is_empty());
+    }
+
+    #[test]
+    fn file_breakpoints_only_hit_their_file() {
+        let mut store = BreakpointStore::default();
+        let ids = store.replace_file("/default.nix", &[(10, None)]);
+        store.relocate(ids[0], Ok((10, None)));
+
+        assert!(store
+            .reached(&location("/lib/imported.nix", 10, 1), true)
+            .is_empty());
+        assert_eq!(
+            entry_ids(store.reached(&location("/default.nix", 10, 1), true)),
+            ids
+        );
+    }
+
+    #[test]
+    fn column_breakpoints_hit_their_expression() {
+        let mut store = BreakpointStore::default();
+        // { a = f x; b = g y; }
+        let ids = store.replace_file("/default.nix", &[(1, Some(16))]);
+        store.relocate(ids[0], Ok((1, Some(16))));
+
+        assert!(store
+            .reached(&location("/default.nix", 1, 7), true)
+            .is_empty());
+        assert_eq!(
+            entry_ids(store.reached(&location("/default.nix", 1, 16), false)),
+            ids
+        );
+    }
+
+    #[test]
+    fn column_breakpoints_say_where_they_moved() {
+        let mut store = BreakpointStore::default();
+        let ids = store.replace_file("/default.nix", &[(1, Some(7)), (2, Some(30))]);
+        store.relocate(ids[0], Ok((1, Some(16))));
+        store.relocate(ids[1], Ok((2, None)));
+
+        let message = |id| store.entries[&id].message.clone();
+        assert_eq!(
+            message(ids[0]).as_deref(),
+            Some("no expression starts at this column, moved to the next one")
+        );
+        assert_eq!(
+            message(ids[1]).as_deref(),
+            Some("no expression at column 30, breaking on the line")
+        );
+    }
+
+    #[test]
+    fn line_breakpoints_bind_to_the_program() {
+        let mut store = BreakpointStore::default();
+        let id = store.add(Breakpoint::Line(3));
+        store.bind_lines("/default.nix");
+
+        assert!(store
+            .reached(&location("/lib/imported.nix", 3, 1), true)
+            .is_empty());
+        assert_eq!(
+            entry_ids(store.reached(&location("/default.nix", 3, 1), true)),
+            vec![id]
+        );
+    }
+
+    #[test]
+    fn hit_conditions_stop_on_matching_hits() {
+        let mut store = BreakpointStore::default();
+        let id = store.add(Breakpoint::Line(3));
+        store.set_hit_condition(id, Some("% 3 == 0".parse().unwrap()));
+
+        let stops: Vec<bool> = (0..6).map(|_| store.record_hit(id)).collect();
+        assert_eq!(stops, vec![false, false, true, false, false, true]);
+    }
+
+    #[test]
+    fn hit_conditions_parse() {
+        let parse = |s: &str| s.parse::<HitCondition>();
+        assert_eq!(parse("5"), Ok(HitCondition::Equal(5)));
+        assert_eq!(parse("== 5"), Ok(HitCondition::Equal(5)));
+        assert_eq!(parse(">= 10"), Ok(HitCondition::GreaterOrEqual(10)));
+        assert_eq!(parse("hit >= 3"), Ok(HitCondition::GreaterOrEqual(3)));
+        assert_eq!(parse("% 100 == 0"), Ok(HitCondition::Multiple(100)));
+        assert!(parse("% 100 == 1").is_err());
+        assert_eq!(parse("% 0"), Err("invalid hit condition `% 0`".to_string()));
+        assert!(parse("% 0 == 0").is_err());
+        assert!(parse("hit == null").is_err());
+    }
+
+    #[test]
+    fn snapshots_are_taken_again_after_changes_only() {
+        let mut store = BreakpointStore::default();
+        let generation = store.generation();
+        let ids = store.replace_file("/default.nix", &[(10, None)]);
+        store.relocate(ids[0], Ok((10, None)));
+        let snapshot = store.snapshot();
+        let seen = generation.load(Ordering::Relaxed);
+
+        let reached = location("/default.nix", 10, 1);
+        assert_eq!(entry_ids(snapshot.reached(&reached, true)), ids);
+        store.record_hit(ids[0]);
+        assert_eq!(generation.load(Ordering::Relaxed), seen);
+
+        store.set_enabled(ids[0], false);
+        assert_ne!(generation.load(Ordering::Relaxed), seen);
+        assert!(store.snapshot().reached(&reached, true).is_empty());
+    }
+
+    #[test]
+    fn function_breakpoints_match_globs() {
+        let mut store = BreakpointStore::default();
+        let ids = store.replace_functions(&["mk*", "fetchurl"]);
+
+        assert_eq!(
+            entry_ids(store.reached_function("mkDerivation")),
+            vec![ids[0]]
+        );
+        assert_eq!(
+            entry_ids(store.reached_function("builtins.fetchurl")),
+            vec![ids[1]]
+        );
+        assert!(store.reached_function("callPackageWith").is_empty());
+        assert!(store
+            .reached(&location("/default.nix", 1, 1), true)
+            .is_empty());
+
+        let new_ids = store.replace_functions(&["fetchurl"]);
+        assert_eq!(new_ids, vec![ids[1]]);
+        assert!(store.get(ids[0]).is_none());
+    }
+
+    #[test]
+    fn replace_file_keeps_ids() {
+        let mut store = BreakpointStore::default();
+        let ids = store.replace_file("default.nix", &[(1, None), (4, None)]);
+        let other = store.add(Breakpoint::Line(7));
+
+        let new_ids = store.replace_file("default.nix", &[(4, None), (9, None)]);
+        assert_eq!(new_ids[0], ids[1]);
+        assert!(store.get(ids[0]).is_none());
+        assert!(store.get(new_ids[1]).is_some());
+        assert!(store.get(other).is_some());
+    }
+
+    #[test]
+    fn relocating_reports_changes() {
+        let mut store = BreakpointStore::default();
+        let ids = store.replace_file("/default.nix", &[(3, None)]);
+        let loc = location("/default.nix", 4, 1);
+        assert!(store.reached(&loc, true).is_empty());
+
+        assert!(store.relocate(ids[0], Ok((4, None))));
+        assert_eq!(entry_ids(store.reached(&loc, true)), ids);
+        assert!(!store.relocate(ids[0], Ok((4, None))));
+
+        // the file changed and lost its code
+        assert!(store.relocate(ids[0], Err("no code on or after line 3".into())));
+        let entry = store.get(ids[0]).unwrap();
+        assert!(!entry.verified);
+        assert_eq!(entry.location.line(), Some(3));
+        assert!(store.reached(&loc, true).is_empty());
+    }
+}
@@ -1,26 +1,193 @@
-use debug_types::types::Capabilities;
+use debug_types::{
+    requests::{
+        BreakpointLocationsArguments, SetBreakpointsArguments, SetExceptionBreakpointsArguments,
+        SetFunctionBreakpointsArguments,
+    },
+    types::{Breakpoint as DapBreakpoint, BreakpointLocation, Capabilities},
+};
 use serde::{Deserialize, Serialize};
-use std::str::FromStr;
-use strum::Display;
+use std::{path::Path, str::FromStr};
+use strum::{Display, EnumString};
 
-use crate::serde_smolstr::SerSmolStr;
+use crate::{
+    breakpoints::{BreakpointEntry, HitCondition},
+    complete::Completion,
+    exceptions::{ExceptionInfo, ExceptionKind},
+    locations::Location,
+    serde_smolstr::SerSmolStr,
+    stack::{Scope, StackFrame},
+    variables::Variable,
+};
+
+/// How far a next or step in goes
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, Display, EnumString,
+)]
+#[strum(serialize_all = "lowercase")]
+pub enum SteppingGranularity {
+    /// a single op
+    Instruction,
+    /// to the next distinct span
+    #[default]
+    Statement,
+    /// to the next source line
+    Line,
+}
+
+/// Where an expression to evaluate comes from, which decides what it may do
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, Display, EnumString,
+)]
+#[strum(serialize_all = "lowercase")]
+pub enum EvaluateContext {
+    /// typed in the debug console, any expression
+    #[default]
+    Repl,
+    /// a watch expression, any expression
+    Watch,
+    /// the text under the mouse, only variables and attribute paths that
+    /// are already evaluated, nothing is forced
+    Hover,
+    /// copying a value, any expression
+    Clipboard,
+}
+
+impl EvaluateContext {
+    /// Whether any expression may be evaluated, not just attribute paths
+    pub fn allows_code(&self) -> bool {
+        *self != EvaluateContext::Hover
+    }
+}
 
 // TODO: support breakpoints on variable names
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Breakpoint {
     Line(usize),
-    FileLine { file: SerSmolStr, line: usize },
+    FileLine {
+        file: SerSmolStr,
+        line: usize,
+        /// only stop when an expression starting on this column executes
+        column: Option<usize>,
+    },
+    /// entry into a lambda or builtin whose name matches the glob pattern
+    Function(SerSmolStr),
+}
+
+impl Breakpoint {
+    pub fn line(&self) -> Option<usize> {
+        match self {
+            Breakpoint::Line(line) => Some(*line),
+            Breakpoint::FileLine { line, .. } => Some(*line),
+            Breakpoint::Function(_) => None,
+        }
+    }
+
+    /// Whether executing an op at `location` hits this breakpoint.
+    /// `entered_line` tells if the previous op was on a different line, line
+    /// breakpoints only fire when arriving on their line, column breakpoints
+    /// whenever an op starts on their column. Breakpoints without a file,
+    /// only set before there is a program, match every file
+    pub fn matches(&self, location: &Location, entered_line: bool) -> bool {
+        match self {
+            Breakpoint::Line(line) => entered_line && *line == location.line,
+            Breakpoint::FileLine { file, line, column } => {
+                let column_matches = match column {
+                    Some(column) => *column == location.column,
+                    None => entered_line,
+                };
+                column_matches && *line == location.line && Path::new(&**file) == &*location.file
+            }
+            Breakpoint::Function(_) => false,
+        }
+    }
+
+    /// Whether entering the function `name` hits this breakpoint, builtins
+    /// match both with and without their `builtins.` prefix
+    pub fn matches_function(&self, name: &str) -> bool {
+        match self {
+            Breakpoint::Function(pattern) => {
+                glob_match(pattern, name)
+                    || name
+                        .strip_prefix("builtins.")
+                        .is_some_and(|name| glob_match(pattern, name))
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Match `name` against a pattern where `*` stands for any sequence of
+/// characters and `?` for any single one
+pub fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    // where the last `*` was, and the position in name it matched up to
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                // let the `*` swallow one more character
+                Some((star, matched)) => {
+                    backtrack = Some((star, matched + 1));
+                    p = star + 1;
+                    n = matched + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+impl std::fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Breakpoint::Line(line) => write!(f, "line {}", line),
+            Breakpoint::FileLine {
+                file,
+                line,
+                column: None,
+            } => write!(f, "{}:{}", &**file, line),
+            Breakpoint::FileLine {
+                file,
+                line,
+                column: Some(column),
+            } => write!(f, "{}:{}:{}", &**file, line, column),
+            Breakpoint::Function(name) => write!(f, "fn {}", &**name),
+        }
+    }
 }
 
 impl FromStr for Breakpoint {
     type Err = ();
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        if let Some((file, line)) = s.split_once(':') {
+        if let Some(name) = s.strip_prefix("fn ") {
+            let name = name.trim();
+            if name.is_empty() {
+                return Err(());
+            }
+            Ok(Breakpoint::Function(name.into()))
+        } else if let Some((file, position)) = s.split_once(':') {
+            let (line, column) = match position.split_once(':') {
+                Some((line, column)) => (line, Some(column.parse::<usize>().map_err(|_| ())?)),
+                None => (position, None),
+            };
             line.parse::<usize>()
                 .map(|l| Breakpoint::FileLine {
                     file: file.into(),
                     line: l,
+                    column,
                 })
                 .map_err(|_| ())
         } else {
@@ -35,10 +202,79 @@ pub enum Command {
     Unknown,
     Initialize, //FIXME: Initialize seems to be adapter only, if not, it's here
     Continue,
-    Launch(SerSmolStr),
+    Launch {
+        program: SerSmolStr,
+        stop_on_entry: bool,
+    },
     Step,
-    Break(Breakpoint),
+    Next(SteppingGranularity),
+    StepIn(SteppingGranularity),
+    StepOut,
+    Pause,
+    Break {
+        location: Breakpoint,
+        condition: Option<SerSmolStr>,
+        hit_condition: Option<HitCondition>,
+    },
+    SetBreakpoints(SetBreakpointsArguments),
+    SetFunctionBreakpoints(SetFunctionBreakpointsArguments),
+    SetExceptionBreakpoints(SetExceptionBreakpointsArguments),
+    BreakpointLocations(BreakpointLocationsArguments),
+    Breakpoints,
+    Delete(usize),
+    Enable(usize),
+    Disable(usize),
     Print(SerSmolStr),
+    /// the frames of the paused program from the top, `levels` of them
+    /// (all if unset) after skipping `start_frame`
+    StackTrace {
+        start_frame: usize,
+        levels: Option<usize>,
+    },
+    /// the scopes of a frame of the paused program, the top one if unset
+    Scopes(Option<usize>),
+    /// `count` children (all if unset) of a scope or variable, from `start`
+    Variables {
+        reference: usize,
+        start: usize,
+        count: Option<usize>,
+    },
+    /// evaluate an expression in the scope of a frame of the paused
+    /// program, the top one if unset
+    Evaluate {
+        expression: SerSmolStr,
+        frame_id: Option<usize>,
+        context: EvaluateContext,
+    },
+    /// names completing the word of `text` before `cursor` (in chars), in
+    /// the scope of a frame of the paused program, the top one if unset
+    Completions {
+        text: String,
+        cursor: usize,
+        frame_id: Option<usize>,
+    },
+    /// the exception the paused program is stopped at
+    ExceptionInfo,
+    /// stop the program, the debugger stays
+    Terminate,
+    /// end the session, stopping the program or letting it run to its end
+    Disconnect {
+        terminate_debuggee: bool,
+    },
+    /// replace the unforced variable `name` of a scope or variable by the
+    /// value of the expression `value`
+    SetVariable {
+        reference: usize,
+        name: String,
+        value: String,
+    },
+    /// replace the unforced attribute path `expression` as seen from a
+    /// frame, the top one if unset, by the value of the expression `value`
+    SetExpression {
+        expression: SerSmolStr,
+        value: String,
+        frame_id: Option<usize>,
+    },
 }
 
 impl FromStr for Command {
@@ -57,24 +293,124 @@ impl FromStr for Command {
             "continue" | "c" => Ok(Command::Continue),
             "initialize" | "init" | "i" => Ok(Command::Initialize),
             "launch" | "l" => {
-                if let Some(target) = arg {
-                    // TODO: don't explode in case of invalid string
-                    Ok(Command::Launch(target.into()))
+                // launch [--stop-on-entry] <file>
+                let (stop_on_entry, target) = match arg {
+                    Some(arg) => match arg.strip_prefix("--stop-on-entry") {
+                        Some(target) => (true, Some(target.trim())),
+                        None => (false, Some(arg)),
+                    },
+                    None => (false, None),
+                };
+                if let Some(target) = target.filter(|target| !target.is_empty()) {
+                    Ok(Command::Launch {
+                        program: target.into(),
+                        stop_on_entry,
+                    })
                 } else {
-                    println!("Err: break missing argument -- provide function name");
+                    println!("Err: launch missing argument -- provide the file to evaluate");
                     Err(())
                 }
             }
             "step" | "s" => Ok(Command::Step),
+            "next" | "n" | "in" | "stepin" => {
+                // next [instruction|statement|line]
+                let granularity = match arg {
+                    Some(granularity) => match granularity.parse() {
+                        Ok(granularity) => granularity,
+                        Err(_) => {
+                            println!("Err: unknown granularity {}", granularity);
+                            return Err(());
+                        }
+                    },
+                    None => SteppingGranularity::default(),
+                };
+                if cmd.as_str() == "next" || cmd.as_str() == "n" {
+                    Ok(Command::Next(granularity))
+                } else {
+                    Ok(Command::StepIn(granularity))
+                }
+            }
+            "out" | "stepout" | "finish" => Ok(Command::StepOut),
+            "pause" => Ok(Command::Pause),
             "break" | "b" => {
                 if let Some(target) = arg {
-                    // TODO: don't explode in case of invalid string
-                    Ok(Command::Break(target.parse().unwrap()))
+                    // break <location> [if <condition> | if hit <hit condition>]
+                    let (location, condition) = match target.split_once(" if ") {
+                        Some((location, condition)) => (location, Some(condition.trim())),
+                        None => (target, None),
+                    };
+                    let Ok(location) = location.trim().parse() else {
+                        println!("Err: invalid breakpoint location {}", location);
+                        return Err(());
+                    };
+                    // `hit == null` is still a condition on a variable named hit
+                    let hit_condition = condition
+                        .filter(|c| c.starts_with("hit"))
+                        .and_then(|c| c.parse::<HitCondition>().ok());
+                    let condition = condition.filter(|_| hit_condition.is_none());
+                    Ok(Command::Break {
+                        location,
+                        condition: condition.map(Into::into),
+                        hit_condition,
+                    })
                 } else {
                     println!("Err: break missing argument -- provide function name");
                     Err(())
                 }
             }
+            "breakpoints" | "info" => Ok(Command::Breakpoints),
+            "delete" | "d" | "enable" | "disable" => {
+                let Some(id) = arg.and_then(|id| id.parse::<usize>().ok()) else {
+                    println!("Err: {} missing argument -- provide breakpoint id", cmd);
+                    return Err(());
+                };
+                match cmd.as_str() {
+                    "enable" => Ok(Command::Enable(id)),
+                    "disable" => Ok(Command::Disable(id)),
+                    _ => Ok(Command::Delete(id)),
+                }
+            }
+            "backtrace" | "bt" | "where" => Ok(Command::StackTrace {
+                start_frame: 0,
+                levels: arg.and_then(|levels| levels.parse().ok()),
+            }),
+            "scopes" => Ok(Command::Scopes(arg.and_then(|frame| frame.parse().ok()))),
+            "vars" | "variables" => {
+                let Some(reference) = arg.and_then(|reference| reference.parse().ok()) else {
+                    println!("Err: vars missing argument -- provide a variables reference");
+                    return Err(());
+                };
+                Ok(Command::Variables {
+                    reference,
+                    start: 0,
+                    count: None,
+                })
+            }
+            "eval" => {
+                if let Some(expression) = arg {
+                    Ok(Command::Evaluate {
+                        expression: expression.into(),
+                        frame_id: None,
+                        context: EvaluateContext::Repl,
+                    })
+                } else {
+                    println!("Err: eval missing argument -- provide an expression");
+                    Err(())
+                }
+            }
+            "exception" | "ex" => Ok(Command::ExceptionInfo),
+            "set" => {
+                // set <attribute path> = <expression>
+                let Some((path, value)) = arg.and_then(|arg| arg.split_once('=')) else {
+                    println!("Err: set missing argument -- provide <variable> = <expression>");
+                    return Err(());
+                };
+                Ok(Command::SetExpression {
+                    expression: path.trim().into(),
+                    value: value.trim().to_string(),
+                    frame_id: None,
+                })
+            }
             "print" | "p" => {
                 if let Some(target) = arg {
                     Ok(Command::Print(target.into()))
@@ -95,27 +431,174 @@ pub enum CommandReply {
     InitializeReply(Capabilities),
     LaunchReply,
     StepReply,
-    BreakReply,
-    PrintReply,
+    PauseReply,
+    BreakReply(usize),
+    BreakpointsReply(Vec<BreakpointEntry>),
+    SetBreakpointsReply(Vec<DapBreakpoint>),
+    SetFunctionBreakpointsReply(Vec<DapBreakpoint>),
+    SetExceptionBreakpointsReply(Vec<DapBreakpoint>),
+    BreakpointLocationsReply(Vec<BreakpointLocation>),
+    PrintReply(Result<String, String>),
     ContinueReply,
+    /// the requested frames, and how many there are in total
+    StackTraceReply {
+        frames: Vec<StackFrame>,
+        total: usize,
+    },
+    ScopesReply(Vec<Scope>),
+    VariablesReply(Result<Vec<Variable>, String>),
+    /// the value of an evaluated expression, named after it
+    EvaluateReply(Result<Variable, String>),
+    /// nothing to complete while the program runs
+    CompletionsReply(Vec<Completion>),
+    ExceptionInfoReply(Result<ExceptionInfo, String>),
+    TerminateReply,
+    DisconnectReply,
+    SetVariableReply(Result<Variable, String>),
+    SetExpressionReply(Result<Variable, String>),
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum ObserverCommand {
+    /// stop the program where it is
     Exit,
+    /// never stop the program again, it runs to its end
+    Detach,
     Wait,
     Done,
-    Launch(SerSmolStr),
+    Launch {
+        program: SerSmolStr,
+        stop_on_entry: bool,
+    },
     Continue,
     Step,
-    Break(Breakpoint),
+    /// step over calls
+    Next(SteppingGranularity),
+    StepIn(SteppingGranularity),
+    StepOut,
     Print(SerSmolStr),
+    /// answered while paused, with all the frames
+    StackTrace,
+    /// answered while paused, with the scopes of a frame
+    Scopes(Option<usize>),
+    /// answered while paused, with the children of a scope or variable
+    Variables {
+        reference: usize,
+        start: usize,
+        count: Option<usize>,
+    },
+    /// answered while paused, with the value of the expression
+    Evaluate {
+        expression: SerSmolStr,
+        frame_id: Option<usize>,
+        context: EvaluateContext,
+    },
+    /// answered while paused, with the names completing the text
+    Completions {
+        text: String,
+        cursor: usize,
+        frame_id: Option<usize>,
+    },
+    /// answered while paused, with the exception it is stopped at
+    ExceptionInfo,
+    /// answered while paused, with the overridden variable
+    SetVariable {
+        reference: usize,
+        name: String,
+        value: String,
+    },
+    /// answered while paused, with the overridden attribute path
+    SetExpression {
+        expression: SerSmolStr,
+        value: String,
+        frame_id: Option<usize>,
+    },
 }
 
 #[derive(Debug)]
 pub enum ObserverReply {
     State,
     Done,
+    /// the frames of the program, from the top
+    StackTrace(Vec<StackFrame>),
+    Scopes(Vec<Scope>),
+    Variables(Result<Vec<Variable>, String>),
+    Evaluated(Result<Variable, String>),
+    Completions(Vec<Completion>),
+    /// none unless stopped on an exception
+    ExceptionInfo(Option<ExceptionInfo>),
+    /// the variable after replacing its value
+    Overridden(Result<Variable, String>),
+    /// a variable rendered for the user
+    Printed(Result<String, String>),
+}
+
+/// Why the observer paused the program
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum StopReason {
+    Entry,
+    Step,
+    /// the user interrupted the program
+    Pause,
+    /// ids of the source breakpoints that were hit
+    Breakpoint(Vec<usize>),
+    /// ids of the function breakpoints that were hit
+    FunctionBreakpoint(Vec<usize>),
+    Exception(ExceptionKind),
+}
+
+impl StopReason {
+    pub fn hit_breakpoint_ids(&self) -> &[usize] {
+        match self {
+            StopReason::Breakpoint(ids) | StopReason::FunctionBreakpoint(ids) => ids,
+            _ => &[],
+        }
+    }
+}
+
+/// Things the observer reports while the program runs, without being asked
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ObserverEvent {
+    /// text for the user, e.g. errors in breakpoint conditions
+    Output(String),
+    /// the program paused, at the start of a span of `file` unless paused
+    /// outside of the code (in a builtin)
+    Stopped {
+        reason: StopReason,
+        description: String,
+        file: Option<String>,
+        line: Option<usize>,
+        column: Option<usize>,
+    },
+    /// a breakpoint moved or became (un)verified when the program loaded
+    /// its file
+    Breakpoint(BreakpointEntry),
+    /// the evaluation finished, 0 if it succeeded
+    Exited(i64),
+    /// the program was stopped before it finished
+    Terminated,
+}
+
+impl std::fmt::Display for ObserverEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ObserverEvent::Output(text) => write!(f, "{}", text),
+            ObserverEvent::Stopped {
+                description,
+                file: Some(file),
+                line: Some(line),
+                column: Some(column),
+                ..
+            } => write!(f, "{} at {}:{}:{}", description, file, line, column),
+            ObserverEvent::Stopped { description, .. } => write!(f, "{}", description),
+            ObserverEvent::Breakpoint(entry) => match &entry.message {
+                Some(message) => write!(f, "Breakpoint {}: {}", entry, message),
+                None => write!(f, "Breakpoint {}", entry),
+            },
+            ObserverEvent::Exited(code) => write!(f, "Program exited with code {}", code),
+            ObserverEvent::Terminated => write!(f, "Program terminated"),
+        }
+    }
 }
 
 // FIXME why does capabilities not implement default?
@@ -0,0 +1,196 @@
+use serde::{Deserialize, Serialize};
+use smol_str::SmolStr;
+use strum::Display;
+use tvix_eval::Value;
+
+use crate::{
+    stack::ScopeKind,
+    variables::{attrs_bindings, evaluated, is_unforced},
+};
+
+/// What a completed name stands for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Display)]
+#[strum(serialize_all = "lowercase")]
+pub enum CompletionKind {
+    /// a binding in scope
+    Variable,
+    /// an attribute of a set
+    Property,
+    /// a lambda or builtin
+    Function,
+    /// the `builtins` set
+    Module,
+}
+
+/// A name completing the word before the cursor, which starts at `start`
+/// (in chars, from 0) and is `length` chars long
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Completion {
+    pub label: String,
+    pub kind: CompletionKind,
+    pub start: usize,
+    pub length: usize,
+}
+
+impl std::fmt::Display for Completion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({})", self.label, self.kind)
+    }
+}
+
+/// Complete the identifier or attribute path ending at `cursor` (in chars)
+/// of `text` with the names of `scopes`, ordered as the scopes of a frame
+/// are: `a.b.c` completes the attributes of `a.b` when it is an already
+/// forced set, `builtins.` the builtins, anything else the bindings in
+/// scope. Nothing is forced
+pub fn complete(
+    text: &str,
+    cursor: usize,
+    scopes: &[(String, ScopeKind, Vec<(SmolStr, Value)>)],
+) -> Vec<Completion> {
+    let before: Vec<char> = text.chars().take(cursor).collect();
+    let word_start = before
+        .iter()
+        .rposition(|&c| !(c.is_ascii_alphanumeric() || "_'-.".contains(c)))
+        .map_or(0, |i| i + 1);
+    let word: String = before[word_start..].iter().collect();
+    if word.starts_with(|c: char| c.is_ascii_digit() || "'-.".contains(c)) {
+        return vec![];
+    }
+
+    let mut path: Vec<&str> = word.split('.').collect();
+    let partial = path.pop().unwrap_or_default();
+    let start = word_start + word.len() - partial.len();
+    let completion = |label: &SmolStr, kind| Completion {
+        label: label.to_string(),
+        kind,
+        start,
+        length: partial.len(),
+    };
+
+    let lexical = scopes
+        .iter()
+        .filter(|(_, kind, _)| *kind != ScopeKind::Builtins)
+        .flat_map(|(_, _, bindings)| bindings);
+    let mut completions: Vec<Completion> = match path.split_first() {
+        None => {
+            let builtins = SmolStr::from("builtins");
+            let mut names: Vec<&SmolStr> = lexical.map(|(name, _)| name).collect();
+            names.push(&builtins);
+            names.sort();
+            // inner bindings shadow outer ones of the same name
+            names.dedup();
+            names
+                .into_iter()
+                .filter(|name| name.starts_with(partial))
+                .map(|name| {
+                    let kind = match name.as_str() {
+                        "builtins" => CompletionKind::Module,
+                        _ => CompletionKind::Variable,
+                    };
+                    completion(name, kind)
+                })
+                .collect()
+        }
+        Some((first, rest)) => {
+            let bindings = match lexical.clone().find(|(name, _)| name == first) {
+                Some((_, value)) => attrs_at(value, rest),
+                None if *first == "builtins" => scopes
+                    .iter()
+                    .find(|(_, kind, _)| *kind == ScopeKind::Builtins)
+                    .filter(|_| rest.is_empty())
+                    .map(|(_, _, bindings)| bindings.clone()),
+                None => None,
+            };
+            bindings
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|(name, _)| name.starts_with(partial))
+                .map(|(name, value)| {
+                    let kind = match evaluated(&value) {
+                        Value::Closure(_) | Value::Builtin(_) => CompletionKind::Function,
+                        _ => CompletionKind::Property,
+                    };
+                    completion(&name, kind)
+                })
+                .collect()
+        }
+    };
+    completions.sort_by(|a, b| a.label.cmp(&b.label));
+    completions
+}
+
+/// The attributes of the set at `path` below `value`, if it and the sets
+/// on the way are already forced
+fn attrs_at(value: &Value, path: &[&str]) -> Option<Vec<(SmolStr, Value)>> {
+    let mut value = value.clone();
+    for name in path {
+        if is_unforced(&value) {
+            return None;
+        }
+        value = attrs_bindings(&value)?
+            .into_iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| value)?;
+    }
+    if is_unforced(&value) {
+        return None;
+    }
+    attrs_bindings(&value)
+}
+
+#[cfg(test)]
+mod tests {
+    use tvix_eval::{EvalMode, Evaluation};
+
+    use super::*;
+
+    fn strict(code: &str) -> Value {
+        Evaluation::builder_pure()
+            .mode(EvalMode::Strict)
+            .build()
+            .evaluate(code, None)
+            .value
+            .unwrap()
+    }
+
+    fn labels(completions: Vec<Completion>) -> Vec<String> {
+        completions.into_iter().map(|c| c.label).collect()
+    }
+
+    #[test]
+    fn completes_scope_and_attribute_paths() {
+        let config = strict("{ services = { nginx = 1; nix-daemon = 2; ntp = 3; }; }");
+        let scopes = vec![
+            (
+                "Locals".to_string(),
+                ScopeKind::Locals,
+                vec![
+                    ("config".into(), config),
+                    ("count".into(), Value::Integer(1)),
+                ],
+            ),
+            (
+                "Builtins".to_string(),
+                ScopeKind::Builtins,
+                vec![
+                    ("length".into(), Value::Null),
+                    ("lessThan".into(), Value::Null),
+                ],
+            ),
+        ];
+
+        assert_eq!(labels(complete("co", 2, &scopes)), ["config", "count"]);
+        assert_eq!(labels(complete("bu", 2, &scopes)), ["builtins"]);
+
+        let completions = complete("print config.services.n", 23, &scopes);
+        assert_eq!(labels(completions.clone()), ["nginx", "nix-daemon", "ntp"]);
+        assert_eq!((completions[0].start, completions[0].length), (22, 1));
+
+        assert_eq!(
+            labels(complete("builtins.le", 11, &scopes)),
+            ["length", "lessThan"]
+        );
+        assert!(complete("config.nothing.", 15, &scopes).is_empty());
+    }
+}
@@ -0,0 +1,340 @@
+use rustc_hash::FxHashMap;
+use smol_str::SmolStr;
+use tvix_eval::{opcode::UpvalueIdx, value::Thunk, Evaluation, SourceCode, Value};
+
+use crate::render::{render, RenderOptions};
+use crate::scope::free_paths;
+use crate::variables::{attrs_bindings, evaluated, is_unforced};
+
+/// How many values `unforced_within` looks at, an expression has no
+/// business going through a whole package set
+const CHECK_LIMIT: usize = 1000;
+
+/// The values `expr` takes from `scope`, by their path: each variable down
+/// the attributes it selects, as far as they are evaluated. A path selecting
+/// a missing attribute is left out, looking for it forces nothing
+fn used_values(expr: &str, scope: &FxHashMap<SmolStr, Value>) -> Vec<(String, Value)> {
+    let mut used = vec![];
+    'paths: for path in free_paths(expr) {
+        // builtins and globals come from the evaluation of the expression
+        let Some(mut value) = scope.get(&path[0]).cloned() else {
+            continue;
+        };
+        let mut walked = path[0].to_string();
+        for name in &path[1..] {
+            if is_unforced(&value) {
+                break;
+            }
+            let attr = attrs_bindings(&value)
+                .and_then(|bindings| bindings.into_iter().find(|(n, _)| n == name));
+            match attr {
+                Some((_, attr)) => value = attr,
+                None => continue 'paths,
+            }
+            walked = format!("{}.{}", walked, name);
+        }
+        used.push((walked, value));
+    }
+    used
+}
+
+/// The thunks within `value`, the value of `path`, that the program didn't
+/// force yet, by their path. Sets, lists, forced thunks and what functions
+/// captured are looked through, up to `CHECK_LIMIT` values: `false` if
+/// there were more
+fn unforced_within(value: &Value, path: &str) -> (Vec<(String, Thunk)>, bool) {
+    let mut pending = vec![(value.clone(), path.to_string())];
+    let mut seen: Vec<Thunk> = vec![];
+    let mut unforced = vec![];
+    let mut checked = 0;
+    while let Some((value, path)) = pending.pop() {
+        checked += 1;
+        if checked > CHECK_LIMIT {
+            return (unforced, false);
+        }
+        match value {
+            Value::Thunk(thunk) if thunk.is_evaluated() => {
+                // recursive sets refer to themselves through their thunks
+                if !seen.iter().any(|s| s.ptr_eq(&thunk)) {
+                    pending.push((thunk.value().clone(), path));
+                    seen.push(thunk);
+                }
+            }
+            Value::Thunk(thunk) => unforced.push((path, thunk)),
+            Value::Attrs(_) => {
+                for (name, value) in attrs_bindings(&value).unwrap_or_default() {
+                    pending.push((value, format!("{}.{}", path, name)));
+                }
+            }
+            Value::List(list) => {
+                for (index, value) in list.iter().enumerate() {
+                    pending.push((value.clone(), format!("{}[{}]", path, index)));
+                }
+            }
+            // calling a function of the program runs its code, which uses
+            // what it captured
+            Value::Closure(closure) => {
+                for (index, name) in closure.lambda.upvalue_names.iter().enumerate() {
+                    let captured = closure.upvalues[UpvalueIdx(index)].clone();
+                    pending.push((captured, format!("{} (captured {})", path, name)));
+                }
+                for namespace in closure.upvalues.with_stack().into_iter().flatten() {
+                    pending.push((namespace.clone(), format!("{} (captured with)", path)));
+                }
+            }
+            _ => (),
+        }
+    }
+    (unforced, true)
+}
+
+/// Check that evaluating `expr` can't force a thunk of the program, found in
+/// `scope`: forcing one runs the program's code ahead of time, and one that
+/// fails stays blackholed for the program. Every value the expression takes
+/// from the scope has to be evaluated all the way
+fn check_read_only(expr: &str, scope: &FxHashMap<SmolStr, Value>) -> Result<(), String> {
+    for (path, value) in used_values(expr, scope) {
+        let (unforced, complete) = unforced_within(&value, &path);
+        if let Some((path, _)) = unforced.first() {
+            return Err(format!("`{}` is not evaluated yet", path));
+        }
+        if !complete {
+            return Err(format!(
+                "`{}` is too large to tell if it is evaluated, select the attributes needed",
+                path
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// The thunks of the program, found in `scope`, that evaluating `expr`
+/// could force, by their path. Only the first values of large sets are
+/// looked at, see `unforced_within`
+pub fn forceable_thunks(expr: &str, scope: &FxHashMap<SmolStr, Value>) -> Vec<(String, Thunk)> {
+    used_values(expr, scope)
+        .into_iter()
+        .flat_map(|(path, value)| unforced_within(&value, &path).0)
+        .collect()
+}
+
+/// Evaluate an expression of a breakpoint, a condition or a `{expr}` of a
+/// log message, with the bindings of `scope` in scope. The result is lazy,
+/// and the expression is refused if it could force a value of the program,
+/// see `check_read_only`
+pub fn evaluate_in_scope(expr: &str, scope: &FxHashMap<SmolStr, Value>) -> Result<Value, String> {
+    check_read_only(expr, scope)?;
+    // a source map of its own, the program's would grow with every hit
+    let eval = Evaluation::builder_impure()
+        .with_source_map(SourceCode::default())
+        .env(Some(scope))
+        .build();
+    let result = eval.evaluate(expr, None);
+
+    if let Some(error) = result.errors.first() {
+        return Err(error.to_string());
+    }
+    result
+        .value
+        .ok_or_else(|| format!("`{}` did not produce a value", expr))
+}
+
+/// Evaluate an expression of the user in the scope of a paused frame. The
+/// result is lazy, the user forces what they look at. It may force thunks
+/// of the program, see `forceable_thunks`. Sharing the source map of the
+/// program lets errors point into the expression
+pub fn evaluate_expression(
+    expr: &str,
+    scope: &FxHashMap<SmolStr, Value>,
+    code: &SourceCode,
+) -> Result<Value, String> {
+    let eval = Evaluation::builder_impure()
+        .with_source_map(code.clone())
+        .env(Some(scope))
+        .build();
+    let result = eval.evaluate(expr, None);
+
+    if let Some(error) = result.errors.first() {
+        return Err(error.to_string());
+    }
+    result
+        .value
+        .ok_or_else(|| format!("`{}` did not produce a value", expr))
+}
+
+/// Evaluate a breakpoint condition, which has to produce a boolean. A
+/// condition using a value the program didn't force yet is an error
+pub fn evaluate_condition(
+    condition: &str,
+    scope: &FxHashMap<SmolStr, Value>,
+) -> Result<bool, String> {
+    match evaluated(&evaluate_in_scope(condition, scope)?) {
+        Value::Bool(b) => Ok(b),
+        other => Err(format!(
+            "condition `{}` evaluated to a {}, expected a bool",
+            condition,
+            other.type_of()
+        )),
+    }
+}
+
+/// Force `thunk` to its value in an evaluation of its own, sharing the
+/// source map of the program for the errors. The thunk keeps its value, the
+/// program won't evaluate it again
+pub fn force_thunk(thunk: &Value, code: &SourceCode) -> Result<(), String> {
+    let mut scope = FxHashMap::default();
+    scope.insert(SmolStr::from("value"), thunk.clone());
+    let eval = Evaluation::builder_impure()
+        .with_source_map(code.clone())
+        .env(Some(&scope))
+        .build();
+    let result = eval.evaluate("builtins.seq value null", None);
+
+    match result.errors.first() {
+        Some(error) => Err(error.to_string()),
+        None => Ok(()),
+    }
+}
+
+/// Make `thunk` evaluate to `value` without running its code. Everything
+/// sharing the thunk sees the new value when it forces it
+pub fn override_thunk(thunk: &Thunk, value: Value) {
+    thunk.set_value(value);
+}
+
+/// The global `builtins` set the program sees, without forcing its values
+pub fn global_builtins() -> Vec<(SmolStr, Value)> {
+    let result = Evaluation::builder_impure()
+        .build()
+        .evaluate("builtins", None);
+    result
+        .value
+        .as_ref()
+        .and_then(attrs_bindings)
+        .unwrap_or_default()
+}
+
+/// Render a value for the debug console, strings are printed without quotes
+/// like `builtins.trace` does. Only what is forced already is shown, as a
+/// summary, a log line is no place for a whole package set
+pub fn render_log_value(value: &Value) -> String {
+    match evaluated(value) {
+        Value::String(s) => String::from_utf8_lossy(s.as_bytes()).into_owned(),
+        other => render(&other, RenderOptions::SUMMARY, None),
+    }
+}
+
+/// Replace every `{expr}` of a logpoint message with what `render` makes of
+/// the expression. `{{` and `}}` stand for literal braces, an unterminated
+/// `{` is kept as is
+pub fn interpolate(message: &str, mut render: impl FnMut(&str) -> String) -> String {
+    let mut output = String::new();
+    let mut chars = message.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' | '}' if chars.peek() == Some(&c) => {
+                chars.next();
+                output.push(c);
+            }
+            '{' => {
+                // the expression may contain braces itself, e.g. attribute sets
+                let mut depth = 1;
+                let mut expr = String::new();
+                for c in chars.by_ref() {
+                    match c {
+                        '{' => depth += 1,
+                        '}' => depth -= 1,
+                        _ => (),
+                    }
+                    if depth == 0 {
+                        break;
+                    }
+                    expr.push(c);
+                }
+                if depth == 0 {
+                    output.push_str(&render(&expr));
+                } else {
+                    output.push('{');
+                    output.push_str(&expr);
+                }
+            }
+            c => output.push(c),
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interpolate_replaces_expressions() {
+        let render = |expr: &str| format!("<{}>", expr.trim());
+        assert_eq!(
+            interpolate("building {pname}-{version}", render),
+            "building <pname>-<version>"
+        );
+        assert_eq!(
+            interpolate("{ { a = 1; }.a } {{literal}}", render),
+            "<{ a = 1; }.a> {literal}"
+        );
+        assert_eq!(interpolate("unterminated {x", render), "unterminated {x");
+    }
+
+    #[test]
+    fn conditions_check_what_they_select() {
+        let program = Evaluation::builder_impure().build().evaluate(
+            "let p = { s = { x = 1 + 1; }; }; in builtins.seq p.s p",
+            None,
+        );
+        let scope: FxHashMap<SmolStr, Value> = program
+            .value
+            .as_ref()
+            .and_then(attrs_bindings)
+            .unwrap()
+            .into_iter()
+            .collect();
+
+        // `s` is a forced set, its attribute is not
+        assert_eq!(
+            evaluate_condition("s.x == 2", &scope),
+            Err("`s.x` is not evaluated yet".to_string())
+        );
+        assert!(evaluate_condition("builtins.attrNames s == [ ]", &scope).is_err());
+        assert!(is_unforced(&attrs_bindings(&scope["s"]).unwrap()[0].1));
+        // looking for a missing attribute forces nothing
+        assert_eq!(evaluate_condition("s.y or true", &scope), Ok(true));
+    }
+
+    #[test]
+    fn conditions_do_not_force_the_program() {
+        let program = Evaluation::builder_impure()
+            .build()
+            .evaluate("{ a = 1 + 1; }", None);
+        let mut scope: FxHashMap<SmolStr, Value> = program
+            .value
+            .as_ref()
+            .and_then(attrs_bindings)
+            .unwrap()
+            .into_iter()
+            .collect();
+        assert!(evaluate_condition("a == 2", &scope).is_err());
+        assert!(is_unforced(&scope["a"]));
+
+        // its own thunks are fine to force
+        assert_eq!(
+            evaluate_condition("let b = 1 + 1; in b == 2", &scope),
+            Ok(true)
+        );
+        scope.insert("c".into(), Value::Integer(2));
+        assert_eq!(evaluate_condition("c == 2", &scope), Ok(true));
+
+        // expressions of the user may force them, those are looked after
+        let forceable: Vec<String> = forceable_thunks("a + c + b", &scope)
+            .into_iter()
+            .map(|(path, _)| path)
+            .collect();
+        assert_eq!(forceable, vec!["a"]);
+    }
+}
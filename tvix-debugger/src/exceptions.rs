@@ -0,0 +1,383 @@
+use std::collections::BTreeSet;
+
+use codemap::Span;
+use codemap_diagnostic::{Diagnostic, Emitter, Level, SpanLabel, SpanStyle};
+use debug_types::types::ExceptionBreakpointsFilter;
+use serde::{Deserialize, Serialize};
+use strum::{Display, EnumIter, EnumString, IntoEnumIterator};
+use tvix_eval::{opcode::Op, SourceCode, Value};
+
+use crate::variables::evaluated;
+
+/// The ways an evaluation can fail that execution can stop at. The observer
+/// never sees the errors themselves, so they are recognized right before the
+/// op (or builtin) that raises them runs, while its frame is still intact.
+/// Errors a builtin raises while it runs, other than `throw` and `abort`,
+/// can't be told in advance and don't stop
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Serialize,
+    Deserialize,
+    Display,
+    EnumString,
+    EnumIter,
+)]
+#[strum(serialize_all = "kebab-case")]
+pub enum ExceptionKind {
+    Throw,
+    Abort,
+    Assert,
+    TypeError,
+    InfiniteRecursion,
+}
+
+impl ExceptionKind {
+    /// Whether `builtins.tryEval` can catch the error
+    pub fn is_catchable(&self) -> bool {
+        matches!(self, ExceptionKind::Throw | ExceptionKind::Assert)
+    }
+
+    /// The variant of tvix's `ErrorKind` the error is raised as
+    pub fn error_kind(&self) -> &'static str {
+        match self {
+            ExceptionKind::Throw => "CatchableError::Throw",
+            ExceptionKind::Abort => "Abort",
+            ExceptionKind::Assert => "CatchableError::AssertionFailed",
+            ExceptionKind::TypeError => "TypeError",
+            ExceptionKind::InfiniteRecursion => "InfiniteRecursion",
+        }
+    }
+
+    fn label(&self) -> &'static str {
+        match self {
+            ExceptionKind::Throw => "throw",
+            ExceptionKind::Abort => "abort",
+            ExceptionKind::Assert => "Failed assertions",
+            ExceptionKind::TypeError => "Operator type errors",
+            ExceptionKind::InfiniteRecursion => "Infinite recursion",
+        }
+    }
+
+    /// What the filter covers, where its label doesn't say it all
+    fn description(&self) -> Option<&'static str> {
+        match self {
+            ExceptionKind::TypeError => Some(
+                "Operands of operators, selected sets and called values of the wrong type. \
+                 Type errors raised inside builtins, e.g. by `builtins.head 1`, don't stop",
+            ),
+            ExceptionKind::InfiniteRecursion => Some(
+                "A value forced again while it is being forced. Builtins forcing their \
+                 arguments, e.g. `builtins.seq`, don't stop on it",
+            ),
+            _ => None,
+        }
+    }
+}
+
+/// The exception execution is stopped at, as the exceptionInfo request
+/// reports it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExceptionInfo {
+    pub kind: ExceptionKind,
+    pub description: String,
+    /// raised inside `builtins.tryEval`, which catches it
+    pub caught: bool,
+    /// what was being evaluated, from the innermost, e.g. `while calling 'f'`
+    pub context: Vec<String>,
+    /// the error and its context with excerpts of the source
+    pub excerpt: String,
+}
+
+impl std::fmt::Display for ExceptionInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.excerpt.trim_end())
+    }
+}
+
+/// The error raised at `span` and the `context` it was raised in, printed
+/// with excerpts of the source like tvix prints its errors
+pub fn excerpt(
+    code: &SourceCode,
+    kind: ExceptionKind,
+    description: &str,
+    span: Option<Span>,
+    context: &[(String, Option<Span>)],
+) -> String {
+    let diagnostic = |level, message: &str, span: Option<Span>| Diagnostic {
+        level,
+        message: message.to_string(),
+        code: None,
+        spans: span
+            .into_iter()
+            .map(|span| SpanLabel {
+                span,
+                label: None,
+                style: SpanStyle::Primary,
+            })
+            .collect(),
+    };
+    let mut diagnostics = vec![Diagnostic {
+        code: Some(kind.error_kind().to_string()),
+        ..diagnostic(Level::Error, description, span)
+    }];
+    diagnostics.extend(
+        context
+            .iter()
+            .map(|(message, span)| diagnostic(Level::Note, message, *span)),
+    );
+
+    let mut out = vec![];
+    {
+        let codemap = code.codemap();
+        Emitter::vec(&mut out, Some(&codemap)).emit(&diagnostics);
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Filter id that restricts the other filters to errors `tryEval` doesn't catch
+pub const UNCAUGHT_FILTER: &str = "uncaught";
+
+/// Which exceptions stop execution, as set by setExceptionBreakpoints
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExceptionFilters {
+    pub kinds: BTreeSet<ExceptionKind>,
+    /// ignore the catchable errors raised inside `builtins.tryEval`
+    pub uncaught_only: bool,
+}
+
+impl ExceptionFilters {
+    /// Filters from their DAP ids, returns the ids that aren't known
+    pub fn from_ids<'a>(ids: impl IntoIterator<Item = &'a str>) -> (Self, Vec<&'a str>) {
+        let mut filters = ExceptionFilters::default();
+        let mut unknown = vec![];
+        for id in ids {
+            if id == UNCAUGHT_FILTER {
+                filters.uncaught_only = true;
+            } else if let Ok(kind) = id.parse::<ExceptionKind>() {
+                filters.kinds.insert(kind);
+            } else {
+                unknown.push(id);
+            }
+        }
+        (filters, unknown)
+    }
+
+    /// Whether an exception of `kind` raised `try_depth` levels of tryEval
+    /// deep stops execution
+    pub fn stops_on(&self, kind: ExceptionKind, try_depth: usize) -> bool {
+        self.kinds.contains(&kind) && !(self.uncaught_only && kind.is_catchable() && try_depth > 0)
+    }
+}
+
+/// The exception filters advertised in the capabilities
+pub fn exception_breakpoint_filters() -> Vec<ExceptionBreakpointsFilter> {
+    ExceptionKind::iter()
+        .map(|kind| ExceptionBreakpointsFilter {
+            filter: kind.to_string(),
+            label: kind.label().to_string(),
+            description: kind.description().map(str::to_string),
+            default: Some(false),
+            supports_condition: None,
+            condition_description: None,
+        })
+        .chain(std::iter::once(ExceptionBreakpointsFilter {
+            filter: UNCAUGHT_FILTER.to_string(),
+            label: "Only uncaught".to_string(),
+            description: Some("Ignore errors caught by builtins.tryEval".to_string()),
+            default: Some(false),
+            supports_condition: None,
+            condition_description: None,
+        }))
+        .collect()
+}
+
+/// The exception raised by calling the builtin `name`, if it always raises one
+pub fn builtin_exception(name: &str) -> Option<ExceptionKind> {
+    match name {
+        "throw" => Some(ExceptionKind::Throw),
+        "abort" => Some(ExceptionKind::Abort),
+        _ => None,
+    }
+}
+
+/// The exception `op` is about to raise given the values on top of the
+/// stack, with a description. Unforced thunks can't be judged, so this only
+/// finds the errors that are certain
+pub fn op_exception(op: &Op, stack: &[Value]) -> Option<(ExceptionKind, String)> {
+    let top = stack.last();
+    let below = stack.len().checked_sub(2).and_then(|i| stack.get(i));
+    let type_error = |value: Option<&Value>, expected: &str, accepts: fn(&Value) -> bool| {
+        let value = value?;
+        if matches!(value, Value::Thunk(_) | Value::Catchable(_)) || accepts(value) {
+            return None;
+        }
+        Some((
+            ExceptionKind::TypeError,
+            format!("expected a {}, but found a {}", expected, value.type_of()),
+        ))
+    };
+    let is_bool = |v: &Value| matches!(v, Value::Bool(_));
+    let is_number = |v: &Value| matches!(v, Value::Integer(_) | Value::Float(_));
+
+    match op {
+        Op::AssertFail => Some((ExceptionKind::Assert, "assertion failed".to_string())),
+        Op::Force => match top? {
+            // forcing a thunk that is already being forced
+            Value::Thunk(thunk) if !thunk.is_evaluated() && !thunk.is_suspended() => Some((
+                ExceptionKind::InfiniteRecursion,
+                "infinite recursion encountered".to_string(),
+            )),
+            _ => None,
+        },
+        Op::Invert | Op::AssertBool | Op::JumpIfFalse | Op::JumpIfTrue => {
+            type_error(top, "bool", is_bool)
+        }
+        Op::Negate => type_error(top, "number", is_number),
+        Op::Sub | Op::Mul | Op::Div => {
+            type_error(below, "number", is_number).or_else(|| type_error(top, "number", is_number))
+        }
+        Op::Add => operands_error(below?, top?, "add", |left, right| {
+            match (left, right) {
+                (l, r) if is_number(l) || is_number(r) => is_number(l) && is_number(r),
+                // strings and paths are joined with anything coercible to one
+                (Value::String(_) | Value::Path(_), r) => is_coercible(r),
+                (l, _) => is_coercible(l),
+            }
+        }),
+        Op::Less | Op::LessOrEq | Op::More | Op::MoreOrEq => {
+            operands_error(below?, top?, "compare", |left, right| match (left, right) {
+                (l, r) if is_number(l) || is_number(r) => is_number(l) && is_number(r),
+                (Value::String(_), Value::String(_))
+                | (Value::Path(_), Value::Path(_))
+                | (Value::List(_), Value::List(_)) => true,
+                _ => false,
+            })
+        }
+        Op::AttrsSelect => type_error(below, "set", |v| matches!(v, Value::Attrs(_))),
+        Op::Call => match top? {
+            Value::Builtin(builtin) => builtin_exception(builtin.name()).map(|kind| {
+                let description = match below.map(evaluated) {
+                    Some(Value::String(message)) => {
+                        String::from_utf8_lossy(message.as_bytes()).into_owned()
+                    }
+                    _ => format!("called builtins.{}", builtin.name()),
+                };
+                (kind, description)
+            }),
+            other => type_error(Some(other), "function", |v| {
+                matches!(v, Value::Closure(_) | Value::Builtin(_) | Value::Attrs(_))
+            }),
+        },
+        _ => None,
+    }
+}
+
+/// Whether `+` can turn `value` into a string
+fn is_coercible(value: &Value) -> bool {
+    matches!(value, Value::String(_) | Value::Path(_) | Value::Attrs(_))
+}
+
+/// The type error of a binary op that `accepts` the operands (forced,
+/// they may be thunks) or not
+fn operands_error(
+    left: &Value,
+    right: &Value,
+    verb: &str,
+    accepts: fn(&Value, &Value) -> bool,
+) -> Option<(ExceptionKind, String)> {
+    let (left, right) = (evaluated(left), evaluated(right));
+    if [&left, &right]
+        .iter()
+        .any(|v| matches!(v, Value::Thunk(_) | Value::Catchable(_)))
+        || accepts(&left, &right)
+    {
+        return None;
+    }
+    Some((
+        ExceptionKind::TypeError,
+        format!(
+            "cannot {} a {} and a {}",
+            verb,
+            left.type_of(),
+            right.type_of()
+        ),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn excerpt_shows_the_error_and_its_context() {
+        let code = SourceCode::default();
+        let file = code.add_file(
+            "test.nix".to_string(),
+            "let a = throw \"x\"; in a".to_string(),
+        );
+        let context = [("while evaluating the attribute 'a'".to_string(), None)];
+        let excerpt = excerpt(
+            &code,
+            ExceptionKind::Throw,
+            "x",
+            Some(file.span.subspan(8, 17)),
+            &context,
+        );
+        assert!(excerpt.contains("error[CatchableError::Throw]: x"));
+        assert!(excerpt.contains("test.nix:1:9"));
+        assert!(excerpt.contains("note: while evaluating the attribute 'a'"));
+    }
+
+    #[test]
+    fn uncaught_only_skips_catchable_errors_in_try_eval() {
+        let (filters, unknown) = ExceptionFilters::from_ids(["throw", "abort", "uncaught", "oops"]);
+        assert_eq!(unknown, vec!["oops"]);
+
+        assert!(filters.stops_on(ExceptionKind::Throw, 0));
+        assert!(!filters.stops_on(ExceptionKind::Throw, 1));
+        // abort can't be caught
+        assert!(filters.stops_on(ExceptionKind::Abort, 1));
+        assert!(!filters.stops_on(ExceptionKind::Assert, 0));
+    }
+
+    #[test]
+    fn filters_say_what_they_miss() {
+        let filters = exception_breakpoint_filters();
+        let type_errors = filters
+            .iter()
+            .find(|filter| filter.filter == "type-error")
+            .unwrap();
+        assert_eq!(type_errors.label, "Operator type errors");
+        assert!(type_errors
+            .description
+            .as_deref()
+            .is_some_and(|d| d.contains("inside builtins")));
+    }
+
+    #[test]
+    fn binary_ops_predict_certain_type_errors() {
+        let kind = |op: Op, stack: &[Value]| op_exception(&op, stack).map(|(kind, _)| kind);
+        let (one, a, yes) = (Value::Integer(1), Value::from("a"), Value::Bool(true));
+
+        assert_eq!(kind(Op::Add, &[one.clone(), one.clone()]), None);
+        assert_eq!(kind(Op::Add, &[a.clone(), a.clone()]), None);
+        assert_eq!(
+            kind(Op::Add, &[a.clone(), one.clone()]),
+            Some(ExceptionKind::TypeError)
+        );
+        assert_eq!(
+            kind(Op::Add, &[yes.clone(), a.clone()]),
+            Some(ExceptionKind::TypeError)
+        );
+        assert_eq!(kind(Op::Less, &[one.clone(), Value::Float(2.0)]), None);
+        assert_eq!(kind(Op::MoreOrEq, &[a.clone(), a.clone()]), None);
+        assert_eq!(kind(Op::Less, &[one, a]), Some(ExceptionKind::TypeError));
+    }
+}
@@ -1,16 +1,71 @@
-use rustyline::{error::ReadlineError, DefaultEditor};
+use std::{cell::RefCell, rc::Rc};
 
-use crate::commands::Command;
+use rustyline::{
+    completion::Completer, error::ReadlineError, highlight::Highlighter, hint::Hinter,
+    history::DefaultHistory, validate::Validator, Context, Editor, Helper,
+};
 
-pub fn handle_input(read_line: &mut DefaultEditor) -> Command {
+use crate::{
+    backend::TvixBackend,
+    commands::{Command, CommandReply},
+};
+
+/// Tab completion of the names in scope of the paused program
+pub struct ReplHelper {
+    backend: Rc<RefCell<TvixBackend>>,
+}
+
+impl ReplHelper {
+    pub fn new(backend: Rc<RefCell<TvixBackend>>) -> Self {
+        ReplHelper { backend }
+    }
+}
+
+impl Completer for ReplHelper {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        let command = Command::Completions {
+            text: line.to_string(),
+            cursor: line[..pos].chars().count(),
+            frame_id: None,
+        };
+        let CommandReply::CompletionsReply(completions) =
+            self.backend.borrow_mut().handle_command(command)
+        else {
+            return Ok((pos, vec![]));
+        };
+        // completions count chars, rustyline wants the byte offset
+        let start = completions.first().map_or(pos, |c| {
+            line.char_indices()
+                .nth(c.start)
+                .map_or(line.len(), |(byte, _)| byte)
+        });
+        Ok((start, completions.into_iter().map(|c| c.label).collect()))
+    }
+}
+
+impl Hinter for ReplHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ReplHelper {}
+
+impl Validator for ReplHelper {}
+
+impl Helper for ReplHelper {}
+
+pub fn handle_input(read_line: &mut Editor<ReplHelper, DefaultHistory>) -> Command {
     let line = read_line.readline(">> ");
 
     match line {
         Ok(text) => match text.trim().parse::<Command>() {
-            Ok(cmd) => {
-                println!("got command {:?}", cmd);
-                cmd
-            }
+            Ok(cmd) => cmd,
             Err(_) => {
                 println!("Unknown command {}", text);
                 Command::Unknown
@@ -1,20 +1,41 @@
+use std::{cell::RefCell, rc::Rc};
+
 use backend::TvixBackend;
-use commands::Command;
+use commands::{Command, CommandReply};
 use config::Args;
-use input::handle_input;
-use rustyline::DefaultEditor;
+use input::{handle_input, ReplHelper};
+use rustyline::{history::DefaultHistory, Editor};
 
 /// dap server
 pub mod backend;
+pub mod breakpoints;
 pub mod commands;
+pub mod complete;
 pub mod config;
+pub mod eval;
+pub mod exceptions;
 pub mod input;
+pub mod locations;
 pub mod observer;
+pub mod render;
+pub mod scope;
 mod serde_smolstr;
+pub mod stack;
+pub mod variables;
 
 pub fn run_debugger(args: Args) {
-    let mut backend = TvixBackend::new(args);
-    let mut read_line = DefaultEditor::new().expect("rl: failure creating editor");
+    let backend = Rc::new(RefCell::new(TvixBackend::new(args)));
+    let events = backend.borrow_mut().events();
+    if let Some(events) = events {
+        std::thread::spawn(move || {
+            for event in events {
+                println!("{}", event);
+            }
+        });
+    }
+    let mut read_line =
+        Editor::<ReplHelper, DefaultHistory>::new().expect("rl: failure creating editor");
+    read_line.set_helper(Some(ReplHelper::new(backend.clone())));
 
     // The main repl loop
     loop {
@@ -22,14 +43,51 @@ pub fn run_debugger(args: Args) {
         match command {
             Command::Unknown => continue,
             Command::Exit => {
-                backend.exit();
+                backend.borrow_mut().exit();
                 break;
             }
 
-            _ => {
-                let reply = backend.handle_command(command);
-                println!("{}", reply);
-            }
+            _ => match backend.borrow_mut().handle_command(command) {
+                CommandReply::BreakpointsReply(breakpoints) => {
+                    for breakpoint in breakpoints {
+                        println!("{}", breakpoint);
+                    }
+                }
+                CommandReply::StackTraceReply { frames, .. } => {
+                    for frame in frames {
+                        println!("{}", frame);
+                    }
+                }
+                CommandReply::ScopesReply(scopes) => {
+                    for scope in scopes {
+                        println!("{}", scope);
+                    }
+                }
+                CommandReply::VariablesReply(Ok(variables)) => {
+                    for variable in variables {
+                        println!("{}", variable);
+                    }
+                }
+                CommandReply::EvaluateReply(Ok(variable))
+                | CommandReply::SetVariableReply(Ok(variable))
+                | CommandReply::SetExpressionReply(Ok(variable)) => println!("{}", variable),
+                CommandReply::ExceptionInfoReply(Ok(info)) => println!("{}", info),
+                CommandReply::CompletionsReply(completions) => {
+                    for completion in completions {
+                        println!("{}", completion);
+                    }
+                }
+                CommandReply::VariablesReply(Err(e))
+                | CommandReply::PrintReply(Err(e))
+                | CommandReply::EvaluateReply(Err(e))
+                | CommandReply::ExceptionInfoReply(Err(e))
+                | CommandReply::SetVariableReply(Err(e))
+                | CommandReply::SetExpressionReply(Err(e)) => {
+                    println!("Err: {}", e)
+                }
+                CommandReply::PrintReply(Ok(value)) => println!("{}", value),
+                reply => println!("{}", reply),
+            },
         }
     }
 }
@@ -0,0 +1,146 @@
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use codemap::Span;
+use tvix_eval::{observer::CompilerObserver, value::Lambda, Evaluation, SourceCode};
+
+/// The path files are identified by, both for breakpoints and in the source
+/// map of the evaluation. Falls back to the path itself if it doesn't exist
+pub fn canonicalize(path: &Path) -> PathBuf {
+    std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// A position in a nix file reached at runtime, lines and columns start at 1
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Location {
+    pub file: Rc<Path>,
+    pub line: usize,
+    pub column: usize,
+}
+
+/// Compiler observer that keeps every chunk produced for a file (the
+/// toplevel, lambdas and thunks), so their spans can be inspected without
+/// running any code
+#[derive(Default)]
+struct ChunkCollector {
+    lambdas: Vec<Rc<Lambda>>,
+}
+
+impl CompilerObserver for ChunkCollector {
+    fn observe_compiled_toplevel(&mut self, lambda: &Rc<Lambda>) {
+        self.lambdas.push(lambda.clone());
+    }
+
+    fn observe_compiled_lambda(&mut self, lambda: &Rc<Lambda>) {
+        self.lambdas.push(lambda.clone());
+    }
+
+    fn observe_compiled_thunk(&mut self, lambda: &Rc<Lambda>) {
+        self.lambdas.push(lambda.clone());
+    }
+}
+
+/// Where in a nix file execution can actually happen, i.e. the spans of all
+/// opcodes in its compiled chunks
+pub struct CodeLocations {
+    code: SourceCode,
+    spans: Vec<Span>,
+}
+
+impl CodeLocations {
+    /// Compile (but don't evaluate) the file at `path`
+    pub fn compile(path: &Path) -> Result<Self, String> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| format!("could not read {}: {}", path.display(), e))?;
+
+        let code = SourceCode::default();
+        let mut collector = ChunkCollector::default();
+        let eval = Evaluation::builder_impure()
+            .with_source_map(code.clone())
+            .compiler_observer(Some(&mut collector))
+            .build();
+        let result = eval.compile_only(contents, Some(path.to_path_buf()));
+
+        if let Some(error) = result.errors.first() {
+            return Err(format!("{} does not compile: {}", path.display(), error));
+        }
+
+        let spans = collector
+            .lambdas
+            .iter()
+            .flat_map(|lambda| lambda.chunk.spans.iter().map(|s| s.span))
+            .collect();
+
+        Ok(CodeLocations { code, spans })
+    }
+
+    /// All (1-indexed) lines on which at least one opcode span starts
+    pub fn lines(&self) -> BTreeSet<usize> {
+        self.positions().into_iter().map(|(line, _)| line).collect()
+    }
+
+    /// All (1-indexed) line and column pairs at which an opcode span starts
+    pub fn positions(&self) -> BTreeSet<(usize, usize)> {
+        let codemap = self.code.codemap();
+        self.spans
+            .iter()
+            .map(|span| {
+                let loc = codemap.look_up_span(*span);
+                (loc.begin.line + 1, loc.begin.column + 1)
+            })
+            .collect()
+    }
+
+    /// Distinct source ranges of all opcode spans, as pairs of (1-indexed)
+    /// start and end line/column positions
+    pub fn ranges(&self) -> BTreeSet<((usize, usize), (usize, usize))> {
+        let codemap = self.code.codemap();
+        self.spans
+            .iter()
+            .map(|span| {
+                let loc = codemap.look_up_span(*span);
+                (
+                    (loc.begin.line + 1, loc.begin.column + 1),
+                    (loc.end.line + 1, loc.end.column + 1),
+                )
+            })
+            .collect()
+    }
+
+    /// Find the line a breakpoint requested on `line` would actually stop
+    /// at: the line itself if it has code, otherwise the next line that does
+    pub fn resolve_line(&self, line: usize) -> Option<usize> {
+        self.lines().range(line..).next().copied()
+    }
+
+    /// Find where a breakpoint requested on `line` and `column` would stop:
+    /// the first expression starting on that line at or after the column.
+    /// Without any, this falls back to a breakpoint on the whole line
+    pub fn resolve_position(&self, line: usize, column: usize) -> Option<(usize, Option<usize>)> {
+        let on_line = self
+            .positions()
+            .range((line, column)..(line + 1, 0))
+            .next()
+            .copied();
+        match on_line {
+            Some((line, column)) => Some((line, Some(column))),
+            None => self.resolve_line(line).map(|line| (line, None)),
+        }
+    }
+
+    /// Where a breakpoint requested on `line`, and `column` if any, stops
+    pub fn resolve(
+        &self,
+        line: usize,
+        column: Option<usize>,
+    ) -> Result<(usize, Option<usize>), String> {
+        let position = match column {
+            Some(column) => self.resolve_position(line, column),
+            None => self.resolve_line(line).map(|line| (line, None)),
+        };
+        position.ok_or_else(|| format!("no code on or after line {}", line))
+    }
+}
@@ -1,23 +1,158 @@
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{HashMap, VecDeque},
     fmt::Display,
-    sync::mpsc::{Receiver, Sender},
+    ops::ControlFlow,
+    path::Path,
+    rc::Rc,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc::{Receiver, Sender},
+        Arc,
+    },
 };
 
 use codemap::Span;
+use rustc_hash::FxHashMap;
+use smol_str::SmolStr;
 use tvix_eval::{
-    chunk::SourceSpan,
     observer::RuntimeObserver,
-    opcode::{CodeIdx, Op},
-    value::Lambda,
+    opcode::{CodeIdx, Op, UpvalueIdx},
+    value::{Lambda, Thunk, Upvalues},
     SourceCode, Value,
 };
 
 use crate::{
-    commands::{Breakpoint, ObserverCommand, ObserverReply},
+    breakpoints::{BreakpointEntry, BreakpointStore, SharedBreakpoints},
+    commands::{
+        Breakpoint, EvaluateContext, ObserverCommand, ObserverEvent, ObserverReply,
+        SteppingGranularity, StopReason,
+    },
+    complete::{complete, Completion},
+    eval::{
+        evaluate_condition, evaluate_expression, evaluate_in_scope, force_thunk, forceable_thunks,
+        global_builtins, interpolate, override_thunk, render_log_value,
+    },
+    exceptions::{
+        builtin_exception, excerpt, op_exception, ExceptionFilters, ExceptionInfo, ExceptionKind,
+    },
+    locations::{canonicalize, CodeLocations, Location},
+    render::{render, RenderOptions},
+    scope::{is_attribute_path, Slot, Syntax},
     serde_smolstr::SerSmolStr,
+    stack::{FrameKind, Scope, ScopeKind, StackFrame},
+    variables::{attrs_bindings, children, evaluated, is_unforced, Container, Registry, Variable},
 };
 
+/// A frame of the VM, as far as the observer can tell from the outside
+struct Frame {
+    kind: FrameKind,
+    name: SmolStr,
+    /// the code running in the frame, native frames have none
+    lambda: Option<Rc<Lambda>>,
+    /// the upvalues of the called closure or forced thunk, when known
+    upvalues: Option<Rc<Upvalues>>,
+    /// arguments the frame was called with, none for thunks and the toplevel
+    arg_count: usize,
+    /// where the locals of the frame start on the stack, only known once the
+    /// frame executes its first op
+    stack_offset: Option<usize>,
+    /// the frame gave control to a generator and will be entered again
+    suspended: bool,
+    /// depth of the frame, as told by the VM
+    call_depth: usize,
+    /// where a generator frame sits on the stack of the VM
+    frame_at: Option<usize>,
+    /// the op the frame is at, for suspended frames the call that is running
+    span: Option<Span>,
+}
+
+impl Frame {
+    fn call(kind: FrameKind, lambda: &Rc<Lambda>, arg_count: usize, call_depth: usize) -> Self {
+        let name = match (&lambda.name, kind) {
+            (Some(name), _) => name.clone(),
+            (None, FrameKind::Toplevel) => "<toplevel>".into(),
+            (None, FrameKind::Thunk) => "<thunk>".into(),
+            (None, _) => "<lambda>".into(),
+        };
+        Frame {
+            kind,
+            name,
+            lambda: Some(lambda.clone()),
+            upvalues: None,
+            arg_count,
+            stack_offset: None,
+            suspended: false,
+            call_depth,
+            frame_at: None,
+            span: None,
+        }
+    }
+
+    fn native(kind: FrameKind, name: SmolStr, frame_at: Option<usize>) -> Self {
+        Frame {
+            kind,
+            name,
+            lambda: None,
+            upvalues: None,
+            arg_count: 0,
+            stack_offset: None,
+            suspended: false,
+            call_depth: 0,
+            frame_at,
+            span: None,
+        }
+    }
+}
+
+/// The upvalues of the closure called or the thunk forced by the last op,
+/// until the frame it enters takes them
+struct Callee {
+    /// the lambda of a closure, a thunk doesn't give its own away
+    lambda: Option<Rc<Lambda>>,
+    upvalues: Rc<Upvalues>,
+}
+
+impl Callee {
+    fn of(value: &Value) -> Option<Callee> {
+        match value {
+            Value::Closure(closure) => Some(Callee {
+                lambda: Some(closure.lambda.clone()),
+                upvalues: closure.upvalues.clone(),
+            }),
+            // copied now, forcing takes them out of the thunk
+            Value::Thunk(thunk) if thunk.is_suspended() => Some(Callee {
+                lambda: None,
+                upvalues: Rc::new(Upvalues::clone(&thunk.upvalues())),
+            }),
+            _ => None,
+        }
+    }
+
+    /// The upvalues for the frame of `lambda` entered with `arg_count`
+    /// arguments, if it is the one called or forced
+    fn enter(self, lambda: &Rc<Lambda>, arg_count: usize) -> Option<Rc<Upvalues>> {
+        let enters = match &self.lambda {
+            Some(callee) => Rc::ptr_eq(callee, lambda),
+            None => arg_count == 0,
+        };
+        enters.then_some(self.upvalues)
+    }
+}
+
+/// Where a next, step in or step out started
+struct StepOrigin {
+    call_depth: usize,
+    span: Option<Span>,
+    location: Option<Location>,
+}
+
+/// How far an attribute path got without forcing anything
+enum Resolved {
+    Value(Value),
+    /// the set at this prefix of the path is not evaluated yet
+    Unforced(String),
+}
+
 // TODO: this doesn't maintain anything, need to maintain the mappings
 // by myself --> hashmap<Name, Value>
 struct ProgramState {
@@ -25,12 +160,6 @@ struct ProgramState {
     stack: Vec<tvix_eval::Value>,
 }
 
-#[derive(Default)]
-struct BreakPoints {
-    breakpoints: HashSet<usize>,
-    _source_id: HashMap<SourceSpan, usize>,
-}
-
 impl Display for ProgramState {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let _ = write!(f, "cur lambda:\n {:?}\n", self.lambda);
@@ -41,9 +170,61 @@ impl Display for ProgramState {
 
 pub struct DebugObserver {
     code: SourceCode,
-    breakpoints: BreakPoints,
+    breakpoints: SharedBreakpoints,
+    /// a snapshot of the store, taken again when its generation changes
+    breakpoint_snapshot: BreakpointStore,
+    breakpoints_generation: Arc<AtomicUsize>,
+    seen_generation: usize,
+    /// set by the backend to stop the running program
+    interrupt: Arc<AtomicBool>,
+    /// tells the backend that the program is paused and answers queries
+    paused: Arc<AtomicBool>,
+    /// where the previously executed op started, a breakpoint only fires
+    /// when execution arrives on its position
+    last_location: Option<Location>,
+    /// canonical paths of the files in the source map, by name
+    files: HashMap<String, Rc<Path>>,
+    /// syntax trees, to find the names of the locals of a frame
+    syntax: Syntax,
+    frames: Vec<Frame>,
+    callee: Option<Callee>,
+    /// what the variables references given out while paused stand for
+    variables: Registry,
+    /// the global builtins, listed once asked for
+    builtins: Option<Vec<(SmolStr, Value)>>,
+    /// thunks the user replaced before the program forced them
+    overrides: Vec<Thunk>,
+    /// name of the lambda whose frame was just entered, until its first op
+    entered_function: Option<SmolStr>,
+    /// how many `builtins.tryEval` calls are running
+    try_depth: usize,
+    /// a copy of the exception filters of the store, refreshed when the
+    /// flag is set
+    exception_filters: ExceptionFilters,
+    exceptions_changed: Arc<AtomicBool>,
+    /// the builtin about to be called raises an exception, which was
+    /// reported at its call already
+    raising: bool,
+    /// the exception execution is stopped at
+    exception: Option<(ExceptionKind, String)>,
+    /// why execution stops at the next pause, stepping if unset
+    stop_reason: Option<StopReason>,
+    /// stop at the first op of user code
+    stop_on_entry: bool,
+    /// the client went away, the program runs to its end without stopping
+    detached: bool,
+    /// the program was terminated, the VM stops at the next op
+    terminated: bool,
+    /// where execution is paused
+    paused_at: Option<Location>,
+    paused_span: Option<Span>,
+    step_origin: Option<StepOrigin>,
     receiver: Receiver<ObserverCommand>,
+    /// commands received while looking for the ones ending the session,
+    /// handled once the program pauses
+    pending: VecDeque<ObserverCommand>,
     _sender: Sender<ObserverReply>,
+    events: Sender<ObserverEvent>,
     cur_cmd: ObserverCommand,
     cur_state: ProgramState,
 }
@@ -51,15 +232,56 @@ pub struct DebugObserver {
 impl DebugObserver {
     pub fn new(
         code: SourceCode,
+        breakpoints: SharedBreakpoints,
+        interrupt: Arc<AtomicBool>,
+        paused: Arc<AtomicBool>,
         receiver: Receiver<ObserverCommand>,
         _sender: Sender<ObserverReply>,
+        events: Sender<ObserverEvent>,
     ) -> Self {
-        let breakpoints = BreakPoints::default();
+        let (exception_filters, exceptions_changed, breakpoint_snapshot, breakpoints_generation) = {
+            let store = breakpoints.lock().unwrap();
+            (
+                store.exception_filters().clone(),
+                store.exceptions_changed(),
+                store.snapshot(),
+                store.generation(),
+            )
+        };
+        let seen_generation = breakpoints_generation.load(Ordering::Relaxed);
         DebugObserver {
             code,
             breakpoints,
+            breakpoint_snapshot,
+            breakpoints_generation,
+            seen_generation,
+            interrupt,
+            paused,
+            last_location: None,
+            files: HashMap::new(),
+            syntax: Syntax::default(),
+            frames: vec![],
+            callee: None,
+            variables: Registry::default(),
+            builtins: None,
+            overrides: vec![],
+            entered_function: None,
+            try_depth: 0,
+            exception_filters,
+            exceptions_changed,
+            raising: false,
+            exception: None,
+            stop_reason: None,
+            stop_on_entry: false,
+            detached: false,
+            terminated: false,
+            paused_at: None,
+            paused_span: None,
+            step_origin: None,
             receiver,
+            pending: VecDeque::new(),
             _sender,
+            events,
             cur_cmd: ObserverCommand::Wait,
             cur_state: ProgramState {
                 lambda: None,
@@ -68,12 +290,14 @@ impl DebugObserver {
         }
     }
 
-    pub fn set_cmd(&mut self, command: ObserverCommand) {
-        self.cur_cmd = command;
+    /// Whether the evaluation ended because the program was terminated,
+    /// rather than running to its end
+    pub fn is_terminated(&self) -> bool {
+        self.terminated
     }
 
-    /// Handling the commands from the backend, can pause execution to wait for
-    /// more user input
+    /// Handling the commands from the backend, pauses execution until a
+    /// command that resumes it (launch, continue, step) is received
     pub fn handle_command(&mut self) -> Result<(), Box<dyn std::error::Error>> {
         // Only stop when we hit a breakpoint || step through the program
         if self.cur_cmd == ObserverCommand::Exit || self.cur_cmd == ObserverCommand::Done {
@@ -81,210 +305,1175 @@ impl DebugObserver {
             return Err("observer: Received done".into());
         }
 
-        let command = self.receiver.recv().unwrap();
+        loop {
+            let command = match self.pending.pop_front() {
+                Some(command) => command,
+                None => self.receiver.recv()?,
+            };
+
+            // nothing runs before the launch, there is nothing to answer
+            if self.cur_cmd == ObserverCommand::Wait
+                && !matches!(
+                    command,
+                    ObserverCommand::Launch { .. }
+                        | ObserverCommand::Exit
+                        | ObserverCommand::Detach
+                )
+            {
+                continue;
+            }
 
-        if command == ObserverCommand::Exit {
-            println!("observer: Received done");
-            self.cur_cmd = ObserverCommand::Exit;
-            return Err("observer: Received done".into());
+            match &command {
+                ObserverCommand::Exit => {
+                    self.cur_cmd = ObserverCommand::Exit;
+                    return Err("observer: Received done".into());
+                }
+                ObserverCommand::Detach => {
+                    self.detach();
+                    return Ok(());
+                }
+                ObserverCommand::Print(path) => {
+                    let printed = self.handle_print(path.clone());
+                    let _ = self._sender.send(ObserverReply::Printed(printed));
+                }
+                ObserverCommand::StackTrace => {
+                    let frames = self.stack_trace();
+                    let _ = self._sender.send(ObserverReply::StackTrace(frames));
+                }
+                ObserverCommand::Scopes(frame_id) => {
+                    let scopes = self.frame_scopes(*frame_id);
+                    let _ = self._sender.send(ObserverReply::Scopes(scopes));
+                }
+                ObserverCommand::Variables {
+                    reference,
+                    start,
+                    count,
+                } => {
+                    let variables = self.list_variables(*reference, *start, *count);
+                    let _ = self._sender.send(ObserverReply::Variables(variables));
+                }
+                ObserverCommand::Evaluate {
+                    expression,
+                    frame_id,
+                    context,
+                } => {
+                    let evaluated = self.evaluate(expression, *frame_id, *context);
+                    let _ = self._sender.send(ObserverReply::Evaluated(evaluated));
+                }
+                ObserverCommand::Completions {
+                    text,
+                    cursor,
+                    frame_id,
+                } => {
+                    let completions = self.completions(text, *cursor, *frame_id);
+                    let _ = self._sender.send(ObserverReply::Completions(completions));
+                }
+                ObserverCommand::ExceptionInfo => {
+                    let info = self.exception_info();
+                    let _ = self._sender.send(ObserverReply::ExceptionInfo(info));
+                }
+                ObserverCommand::SetVariable {
+                    reference,
+                    name,
+                    value,
+                } => {
+                    let overridden = self.set_variable(*reference, name, value);
+                    let _ = self._sender.send(ObserverReply::Overridden(overridden));
+                }
+                ObserverCommand::SetExpression {
+                    expression,
+                    value,
+                    frame_id,
+                } => {
+                    let overridden = self.set_expression(expression, value, *frame_id);
+                    let _ = self._sender.send(ObserverReply::Overridden(overridden));
+                }
+                ObserverCommand::Continue => {
+                    self.handle_continue();
+                    return Ok(());
+                }
+                ObserverCommand::Step => {
+                    self.handle_step();
+                    return Ok(());
+                }
+                ObserverCommand::Next(_)
+                | ObserverCommand::StepIn(_)
+                | ObserverCommand::StepOut => {
+                    self.handle_step_over(command);
+                    return Ok(());
+                }
+                //FIXME: do we need the launch arg?
+                ObserverCommand::Launch { stop_on_entry, .. } => {
+                    self.handle_launch(*stop_on_entry);
+                    return Ok(());
+                }
+                ObserverCommand::Wait => (),
+                ObserverCommand::Done => (),
+            };
         }
+    }
 
-        if self.cur_cmd == ObserverCommand::Wait && !matches!(command, ObserverCommand::Launch(_)) {
-            println!("Program is not running! Launch first");
-            return Ok(());
+    /// Resolve the canonical file and position a span starts at. The file
+    /// is whatever the source map knows it as, which for imports is the
+    /// imported path
+    fn locate(&mut self, span: &Span) -> Location {
+        let (file, line, column) = {
+            let codemap = self.code.codemap();
+            let loc = codemap.look_up_span(*span);
+            // this runs for every op, the name is only copied for new files
+            let file = match self.files.get(loc.file.name()) {
+                Some(file) => Ok(file.clone()),
+                None => Err(loc.file.name().to_string()),
+            };
+            (file, loc.begin.line + 1, loc.begin.column + 1)
+        };
+        let file = match file {
+            Ok(file) => file,
+            Err(name) => {
+                let file: Rc<Path> = canonicalize(Path::new(&name)).into();
+                self.files.insert(name, file.clone());
+                self.verify_breakpoints(&file);
+                file
+            }
+        };
+        Location { file, line, column }
+    }
+
+    /// Verify the breakpoints of a file again once the program loads it, it
+    /// may have changed since they were set. The client is told about the
+    /// ones that moved or became (un)verified
+    fn verify_breakpoints(&mut self, file: &Path) {
+        let name = file.to_string_lossy();
+        let ids = self.breakpoints.lock().unwrap().file_breakpoints(&name);
+        if ids.is_empty() {
+            return;
+        }
+        let locations = CodeLocations::compile(file);
+        let mut breakpoints = self.breakpoints.lock().unwrap();
+        for id in ids {
+            let Some((line, column)) = breakpoints.get(id).and_then(|entry| entry.requested) else {
+                continue;
+            };
+            let resolved = match &locations {
+                Ok(locations) => locations.resolve(line, column),
+                Err(e) => Err(e.clone()),
+            };
+            if breakpoints.relocate(id, resolved) {
+                if let Some(entry) = breakpoints.get(id) {
+                    let _ = self.events.send(ObserverEvent::Breakpoint(entry.clone()));
+                }
+            }
         }
+    }
 
-        match &command {
-            ObserverCommand::Print(smol_str) => self.handle_print(smol_str.clone()),
-            ObserverCommand::Break(smol_str) => self.handle_break(smol_str.clone()),
-            ObserverCommand::Continue => self.handle_continue(),
-            ObserverCommand::Step => self.handle_step(),
-            ObserverCommand::Launch(_) => self.handle_launch(), //FIXME: do we need the launch arg?
-            ObserverCommand::Wait => (),
-            ObserverCommand::Done => (),
-            _ => panic!("observer: unexpected request: {:?}", command),
+    /// The scopes of frame `index` of the paused program with their
+    /// bindings: its locals, the upvalues of its closure or thunk, the
+    /// namespaces of the `with`s around it, innermost first, and the
+    /// builtins. Upvalues are only known for frames entered by an op or a
+    /// builtin that was seen calling or forcing
+    fn scope_bindings(&mut self, index: usize) -> Vec<(String, ScopeKind, Vec<(SmolStr, Value)>)> {
+        let Some(frame) = self.frames.get(index) else {
+            return vec![];
         };
+        let (kind, span, offset, arg_count, lambda, captured) = (
+            frame.kind,
+            frame.span,
+            frame.stack_offset,
+            frame.arg_count,
+            frame.lambda.clone(),
+            frame.upvalues.clone(),
+        );
 
-        Ok(())
+        let mut locals: Vec<(SmolStr, Value)> = vec![];
+        let mut withs: Vec<(String, Value)> = vec![];
+        let mut upvalues: Vec<(SmolStr, Value)> = vec![];
+        if let (true, Some(span), Some(offset)) = (kind.is_code(), span, offset) {
+            let slots = self.syntax.slots(&self.code, span, arg_count > 0);
+            let stack = self.cur_state.stack.get(offset..).unwrap_or_default();
+            for (slot, value) in slots.into_iter().zip(stack) {
+                match slot {
+                    Slot::Local(name) => match locals.iter_mut().find(|(n, _)| *n == name) {
+                        // inner bindings shadow the outer ones
+                        Some(local) => local.1 = value.clone(),
+                        None => locals.push((name, value.clone())),
+                    },
+                    Slot::With(namespace) => withs.push((namespace, value.clone())),
+                    Slot::Hidden => (),
+                }
+            }
+        }
+        if let (Some(captured), Some(lambda)) = (captured, lambda) {
+            // named by the compiler as it resolved them
+            upvalues = lambda
+                .upvalue_names
+                .iter()
+                .enumerate()
+                .map(|(i, name)| (name.clone(), captured[UpvalueIdx(i)].clone()))
+                .collect();
+            if let (Some(with_stack), Some(span)) = (captured.with_stack(), span) {
+                let namespaces = self.syntax.withs(&self.code, span, arg_count > 0);
+                withs.splice(0..0, namespaces.into_iter().zip(with_stack.iter().cloned()));
+            }
+        }
+
+        let mut scopes = vec![("Locals".to_string(), ScopeKind::Locals, locals)];
+        if !upvalues.is_empty() {
+            scopes.push(("Upvalues".to_string(), ScopeKind::Upvalues, upvalues));
+        }
+        for (namespace, value) in withs.into_iter().rev() {
+            // a namespace that isn't forced yet can't be listed
+            let bindings = attrs_bindings(&value).unwrap_or_default();
+            scopes.push((format!("with {}", namespace), ScopeKind::With, bindings));
+        }
+        let builtins = self.builtins.get_or_insert_with(global_builtins).clone();
+        scopes.push(("Builtins".to_string(), ScopeKind::Builtins, builtins));
+        scopes
     }
 
-    // fn print_current_source(&self) {
-    //     let lambda = self.cur_state.lambda.as_ref().unwrap();
-    //     let spans = &lambda.chunk.spans;
-    //
-    //     println!("Lambda chunks spans");
-    //     for span in spans {
-    //         let snippet = self.code.source_slice(span.span);
-    //         let line = self.code.get_line(span.span);
-    //
-    //         println!("snippet: {}, on line {}", snippet, line);
-    //     }
-    //
-    //     // println!("Constants");
-    //     // if let Some(source_span) = spans
-    //     //     .iter()
-    //     //     .filter(|s| s.start <= self.cur_state.ip)
-    //     //     .max_by_key(|s| s.start)
-    //     // {
-    //     //     let span = source_span.span;
-    //     //     let codemap = self.code.codemap();
-    //     //
-    //     //     // Look up the file containing the span
-    //     //     let file = codemap.find_file(span.low());
-    //     //
-    //     //     // Ask the file to extract the source snippet for the span
-    //     //     let snippet = file.source_slice(span);
-    //     //
-    //     //     println!("IP: {}, Cur snippet: {}", self.cur_state.ip, snippet);
-    //     // }
-    // }
-
-    fn is_breakpoint(&mut self, cur_span: &Span) -> bool {
-        // println!(
-        //     "Looking for span {:?}\n all spans: {:?}\n",
-        //     cur_span, &self.breakpoints.breakpoints
-        // );
-        let line = &self.code.get_line(*cur_span);
-        let found = self.breakpoints.breakpoints.contains(line);
-        if found {
-            self.breakpoints.breakpoints.remove(&line);
-            self.cur_cmd = ObserverCommand::Step;
+    /// The scopes of frame `id` (the top one if unset) of the paused
+    /// program, registered for the variables requests
+    fn frame_scopes(&mut self, id: Option<usize>) -> Vec<Scope> {
+        let Some(index) = id.or_else(|| self.frames.len().checked_sub(1)) else {
+            return vec![];
+        };
+        self.scope_bindings(index)
+            .into_iter()
+            .map(|(name, kind, bindings)| Scope {
+                name,
+                kind,
+                named_variables: bindings.len(),
+                variables_reference: self.variables.insert(Container::Bindings(bindings)),
+            })
+            .collect()
+    }
+
+    /// The children of the container behind `reference`, `count` of them
+    /// from `start`. Expanding an unforced thunk forces it
+    fn list_variables(
+        &mut self,
+        reference: usize,
+        start: usize,
+        count: Option<usize>,
+    ) -> Result<Vec<Variable>, String> {
+        let container = self
+            .variables
+            .get(reference)
+            .cloned()
+            .ok_or_else(|| format!("no variables for reference {}", reference))?;
+
+        let entries: Vec<(String, Value)> = match container {
+            Container::Bindings(bindings) => bindings
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), value))
+                .collect(),
+            Container::Value(value) => children(&value),
+            Container::Force { name, thunk } => {
+                return Ok(vec![match self.force(&name, &thunk) {
+                    Ok(value) => self.variable(name, &value),
+                    Err(e) => Variable {
+                        name,
+                        value: format!("<error: {}>", e),
+                        type_name: None,
+                        variables_reference: 0,
+                        named_variables: None,
+                        indexed_variables: None,
+                        lazy: false,
+                        overridden: false,
+                    },
+                }]);
+            }
+        };
+
+        let entries = entries.into_iter().skip(start);
+        let entries: Vec<_> = match count {
+            Some(count) if count > 0 => entries.take(count).collect(),
+            _ => entries.collect(),
+        };
+        Ok(entries
+            .into_iter()
+            .map(|(name, value)| self.variable(name, &value))
+            .collect())
+    }
+
+    /// A variable for `value`, registering what it expands to: sets and
+    /// lists list their children, unforced thunks force themselves
+    fn variable(&mut self, name: String, value: &Value) -> Variable {
+        let overridden = matches!(value, Value::Thunk(thunk)
+            if self.overrides.iter().any(|o| o.ptr_eq(thunk)));
+        let value = evaluated(value);
+        let (container, named_variables, indexed_variables) = match &value {
+            Value::Thunk(thunk) if thunk.is_suspended() => (
+                Some(Container::Force {
+                    name: name.clone(),
+                    thunk: value.clone(),
+                }),
+                None,
+                None,
+            ),
+            Value::Attrs(attrs) if !attrs.is_empty() => (
+                Some(Container::Value(value.clone())),
+                Some(attrs.len()),
+                None,
+            ),
+            Value::List(list) if !list.is_empty() => (
+                Some(Container::Value(value.clone())),
+                None,
+                Some(list.len()),
+            ),
+            _ => (None, None, None),
+        };
+        let lazy = is_unforced(&value);
+        Variable {
+            value: render(&value, RenderOptions::SUMMARY, Some(&self.code)),
+            type_name: (!lazy).then(|| value.type_of().to_string()),
+            variables_reference: container.map_or(0, |c| self.variables.insert(c)),
+            named_variables,
+            indexed_variables,
+            lazy,
+            overridden,
+            name,
+        }
+    }
+
+    /// Force a thunk the user asked for. A fatal error leaves the thunk
+    /// unusable: when the program forces it, it fails with an infinite
+    /// recursion instead of the error, so the user is told about it
+    fn force(&mut self, name: &str, thunk: &Value) -> Result<Value, String> {
+        let result = force_thunk(thunk, &self.code);
+        if !is_unforced(thunk) {
+            // errors the program can catch are the value of the thunk
+            return Ok(evaluated(thunk));
+        }
+        let error = result
+            .err()
+            .unwrap_or_else(|| "the value is not available".to_string());
+        self.warn_unusable(name, &error);
+        Err(error)
+    }
+
+    /// Tell the user that forcing `name` for them failed with `error`, and
+    /// left it unusable for the program
+    fn warn_unusable(&self, name: &str, error: &str) {
+        let _ = self.events.send(ObserverEvent::Output(format!(
+            "forcing {} failed: {}. The program will report infinite recursion if it forces {} itself",
+            name, error, name
+        )));
+    }
+
+    /// The breakpoints to match ops against, the store is only locked when
+    /// they changed
+    fn breakpoint_snapshot(&mut self) -> &BreakpointStore {
+        let generation = self.breakpoints_generation.load(Ordering::Relaxed);
+        if generation != self.seen_generation {
+            self.seen_generation = generation;
+            self.breakpoint_snapshot = self.breakpoints.lock().unwrap().snapshot();
+        }
+        &self.breakpoint_snapshot
+    }
+
+    fn is_breakpoint(&mut self, cur_span: &Span, stack: &[Value]) -> bool {
+        if self.breakpoint_snapshot().is_empty() {
+            return false;
+        }
+
+        let location = self.locate(cur_span);
+        if self.last_location.as_ref() == Some(&location) {
+            return false;
+        }
+        let entered_line = self
+            .last_location
+            .as_ref()
+            .is_none_or(|last| last.file != location.file || last.line != location.line);
+        let location = self.last_location.insert(location);
+
+        let reached = self.breakpoint_snapshot.reached(location, entered_line);
+        self.should_stop(reached, Some(cur_span), stack)
+    }
+
+    /// Function breakpoints hit by entering the lambda or builtin `name`.
+    /// Lambdas are checked at the first op of their frame, so that conditions
+    /// can use the arguments. Builtins have no frame, their conditions are
+    /// evaluated without locals
+    fn is_function_breakpoint(&mut self, name: &str, span: Option<&Span>, stack: &[Value]) -> bool {
+        let reached = self.breakpoint_snapshot().reached_function(name);
+        if reached.is_empty() {
+            return false;
         }
-        found
+        self.should_stop(reached, span, stack)
     }
 
-    fn handle_launch(&mut self) {
+    /// Evaluate the conditions, hit conditions and log messages of reached
+    /// breakpoints, returns whether one of them stops execution
+    fn should_stop(
+        &mut self,
+        reached: Vec<BreakpointEntry>,
+        span: Option<&Span>,
+        stack: &[Value],
+    ) -> bool {
+        let mut hit = vec![];
+        let mut functions = false;
+        for entry in reached {
+            let scope = match span {
+                Some(_) if entry.condition.is_some() || entry.log_message.is_some() => {
+                    // the top frame is at `span` already, the stack is the
+                    // one of this op
+                    self.cur_state.stack = stack.to_owned();
+                    self.expression_scope(self.frames.len().saturating_sub(1))
+                }
+                _ => FxHashMap::default(),
+            };
+            if let Some(condition) = &entry.condition {
+                match evaluate_condition(condition, &scope) {
+                    Ok(true) => (),
+                    Ok(false) => continue,
+                    Err(e) => {
+                        // a broken condition should not take the program down
+                        let _ = self.events.send(ObserverEvent::Output(format!(
+                            "breakpoint #{}: error in condition: {}",
+                            entry.id, e
+                        )));
+                        continue;
+                    }
+                }
+            }
+            if !self.breakpoints.lock().unwrap().record_hit(entry.id) {
+                continue;
+            }
+            // logpoints print and let the program run
+            if let Some(log_message) = &entry.log_message {
+                let output = interpolate(log_message, |expr| {
+                    // a plain name shows its value as it is, forced or not
+                    let value = match scope.get(expr.trim()) {
+                        Some(value) => Ok(value.clone()),
+                        None => evaluate_in_scope(expr, &scope),
+                    };
+                    match value {
+                        Ok(value) => render_log_value(&value),
+                        Err(e) => format!("<error: {}>", e),
+                    }
+                });
+                let _ = self.events.send(ObserverEvent::Output(output));
+                continue;
+            }
+            functions |= matches!(entry.location, Breakpoint::Function(_));
+            hit.push(entry.id);
+        }
+        if hit.is_empty() {
+            return false;
+        }
+        self.stop_reason = Some(if functions {
+            StopReason::FunctionBreakpoint(hit)
+        } else {
+            StopReason::Breakpoint(hit)
+        });
         self.cur_cmd = ObserverCommand::Step;
+        true
+    }
+
+    /// Whether an exception about to be raised stops execution, stopping
+    /// switches to stepping
+    fn is_exception(&mut self, kind: ExceptionKind, description: String) -> bool {
+        let stops = self.exception_filters.stops_on(kind, self.try_depth);
+        if stops {
+            self.exception = Some((kind, description));
+            self.stop_reason = Some(StopReason::Exception(kind));
+            self.cur_cmd = ObserverCommand::Step;
+        }
+        stops
+    }
+
+    /// Report why and where the program stopped, then wait for the command
+    /// that resumes it. `span` is the op about to run, if any, and `stack`
+    /// the stack of the VM as far as it is known
+    fn pause(&mut self, span: Option<&Span>, stack: &[Value]) {
+        if self.detached || self.terminated {
+            return;
+        }
+        let reason = self.stop_reason.take().unwrap_or(StopReason::Step);
+        let ids = |ids: &[usize]| {
+            ids.iter()
+                .map(|id| format!("#{}", id))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        let description = match &reason {
+            StopReason::Entry => "Paused on entry".to_string(),
+            StopReason::Step => "Paused after step".to_string(),
+            StopReason::Pause => "Paused".to_string(),
+            StopReason::Breakpoint(hit) => format!("Paused on breakpoint {}", ids(hit)),
+            StopReason::FunctionBreakpoint(hit) => {
+                format!("Paused on function breakpoint {}", ids(hit))
+            }
+            StopReason::Exception(kind) => match &self.exception {
+                Some((_, description)) => format!("Paused on {}: {}", kind, description),
+                None => format!("Paused on {}", kind),
+            },
+        };
+        let location = span.map(|span| self.locate(span));
+        self.paused_at = location.clone();
+        self.paused_span = span.copied();
+        self.step_origin = None;
+        self.cur_state.stack = stack.to_owned();
+
+        // before the event, a client asks for the stack as soon as it gets it
+        self.paused.store(true, Ordering::Relaxed);
+        let _ = self.events.send(ObserverEvent::Stopped {
+            reason,
+            description,
+            file: location
+                .as_ref()
+                .map(|l| l.file.to_string_lossy().into_owned()),
+            line: location.as_ref().map(|l| l.line),
+            column: location.as_ref().map(|l| l.column),
+        });
+        let _ = self.handle_command();
+        self.paused.store(false, Ordering::Relaxed);
+        self.variables.clear();
+        if self.cur_cmd == ObserverCommand::Exit {
+            self.terminate();
+        }
+    }
+
+    /// Abandon the evaluation: nothing stops anymore, and the next op
+    /// breaks out of the VM, see `observe_execute_op`
+    fn terminate(&mut self) {
+        self.terminated = true;
+        self.cur_cmd = ObserverCommand::Exit;
+    }
+
+    /// Let the program run to its end, nobody is there to see it stop
+    fn detach(&mut self) {
+        self.detached = true;
+        self.cur_cmd = ObserverCommand::Continue;
+    }
+
+    /// The frames of the program from the top, with the span they are at
+    fn stack_trace(&mut self) -> Vec<StackFrame> {
+        let frames: Vec<(usize, SmolStr, FrameKind, Option<Span>)> = self
+            .frames
+            .iter()
+            .enumerate()
+            .rev()
+            .map(|(id, frame)| (id, frame.name.clone(), frame.kind, frame.span))
+            .collect();
+
+        frames
+            .into_iter()
+            .map(|(id, name, kind, span)| {
+                let position = span.map(|span| {
+                    let location = self.locate(&span);
+                    let end = self.code.codemap().look_up_span(span).end;
+                    (location, end.line + 1, end.column + 1)
+                });
+                StackFrame {
+                    id,
+                    name: name.to_string(),
+                    kind,
+                    file: position
+                        .as_ref()
+                        .map(|(l, _, _)| l.file.to_string_lossy().into_owned()),
+                    line: position.as_ref().map(|(l, _, _)| l.line),
+                    column: position.as_ref().map(|(l, _, _)| l.column),
+                    end_line: position.as_ref().map(|(_, line, _)| *line),
+                    end_column: position.as_ref().map(|(_, _, column)| *column),
+                }
+            })
+            .collect()
+    }
+
+    /// The exception execution is stopped at, with what the frames were
+    /// evaluating when it was raised
+    fn exception_info(&mut self) -> Option<ExceptionInfo> {
+        let (kind, description) = self.exception.clone()?;
+        let frames: Vec<(FrameKind, SmolStr, Option<Span>)> = self
+            .frames
+            .iter()
+            .rev()
+            .map(|frame| (frame.kind, frame.name.clone(), frame.span))
+            .collect();
+
+        let mut context = vec![];
+        for (depth, (frame_kind, name, span)) in frames.into_iter().enumerate() {
+            let message = match frame_kind {
+                FrameKind::Thunk => {
+                    let attribute = span.and_then(|s| self.syntax.thunk_attribute(&self.code, s));
+                    match attribute {
+                        Some(attribute) => {
+                            format!("while evaluating the attribute '{}'", attribute)
+                        }
+                        None => "while evaluating a value".to_string(),
+                    }
+                }
+                FrameKind::Lambda if name == "<lambda>" => "while calling a function".to_string(),
+                FrameKind::Lambda | FrameKind::Builtin => format!("while calling '{}'", name),
+                FrameKind::Toplevel => "while evaluating the file".to_string(),
+                FrameKind::Generator => continue,
+            };
+            // the top frame is where the error itself is raised
+            context.push((message, span.filter(|_| depth > 0)));
+        }
+
+        Some(ExceptionInfo {
+            excerpt: excerpt(&self.code, kind, &description, self.paused_span, &context),
+            caught: kind.is_catchable() && self.try_depth > 0,
+            context: context.into_iter().map(|(message, _)| message).collect(),
+            kind,
+            description,
+        })
+    }
+
+    /// Drop the topmost frame whose kind matches, and whatever was left
+    /// above it
+    fn pop_frame(&mut self, is_kind: impl Fn(FrameKind) -> bool) {
+        if let Some(index) = self.frames.iter().rposition(|frame| is_kind(frame.kind)) {
+            self.frames.truncate(index);
+        }
+    }
+
+    /// Run the program, when stopping on entry it stops at the first op of
+    /// a file on disk, skipping the code tvix brings along
+    fn handle_launch(&mut self, stop_on_entry: bool) {
+        self.cur_cmd = ObserverCommand::Continue;
+        self.stop_on_entry = stop_on_entry;
+    }
+
+    /// Run until the step is over, see `step_finished`
+    fn handle_step_over(&mut self, command: ObserverCommand) {
+        self.step_origin = Some(StepOrigin {
+            call_depth: self.call_depth(),
+            span: self.paused_span,
+            location: self.paused_at.clone(),
+        });
+        self.cur_cmd = command;
+        self.exception = None;
+        self.interrupt.store(false, Ordering::Relaxed);
+    }
+
+    /// Depth of the innermost frame running nix code
+    fn call_depth(&self) -> usize {
+        self.frames
+            .iter()
+            .rev()
+            .find(|frame| frame.kind.is_code())
+            .map_or(0, |frame| frame.call_depth)
+    }
+
+    /// Whether the program runs until something stops it, as opposed to
+    /// stepping op by op
+    fn is_running(&self) -> bool {
+        matches!(
+            self.cur_cmd,
+            ObserverCommand::Continue
+                | ObserverCommand::Next(_)
+                | ObserverCommand::StepIn(_)
+                | ObserverCommand::StepOut
+        )
+    }
+
+    /// Whether the op at `span` ends the current step. Next stops after
+    /// moving by the granularity in the same frame or once the frame
+    /// returned, step in also stops in a called function or forced thunk,
+    /// step out only once the frame returned
+    fn step_finished(&mut self, span: &Span) -> bool {
+        let Some(origin) = &self.step_origin else {
+            return true;
+        };
+        let (origin_depth, origin_span, origin_line) = (
+            origin.call_depth,
+            origin.span,
+            origin.location.as_ref().map(|l| (l.file.clone(), l.line)),
+        );
+        let depth = self.call_depth();
+        let moved = |observer: &mut Self, granularity: SteppingGranularity| match granularity {
+            SteppingGranularity::Instruction => true,
+            SteppingGranularity::Statement => origin_span != Some(*span),
+            SteppingGranularity::Line => {
+                let location = observer.locate(span);
+                origin_line != Some((location.file, location.line))
+            }
+        };
+
+        match self.cur_cmd {
+            ObserverCommand::StepIn(granularity) => {
+                depth > origin_depth || moved(self, granularity)
+            }
+            ObserverCommand::Next(granularity) => {
+                depth < origin_depth || (depth == origin_depth && moved(self, granularity))
+            }
+            ObserverCommand::StepOut => depth < origin_depth,
+            _ => true,
+        }
     }
 
     fn handle_continue(&mut self) {
         self.cur_cmd = ObserverCommand::Continue;
+        self.exception = None;
+        // pausing a paused program does nothing
+        self.interrupt.store(false, Ordering::Relaxed);
     }
 
     fn handle_step(&mut self) {
         self.cur_cmd = ObserverCommand::Step;
-        // self.print_current_source();
-        // println!("{}", self.cur_state);
-    }
-
-    fn handle_print(&self, var_name: SerSmolStr) {
-        // TODO if the option is some, find that variable name in the lambda
-        // else print all the variable names
-        if let Some(lambda) = &self.cur_state.lambda {
-            if let Some(name) = &lambda.name {
-                if *name == var_name {
-                    // TODO: get the value here
-                    // println!("FOUND IT {:?}", lambda.chunk);
+        self.exception = None;
+    }
+
+    /// Evaluate `expression` in the scope of frame `id` (the top one if
+    /// unset). Hovering only looks up what is already evaluated, see
+    /// `hover`
+    fn evaluate(
+        &mut self,
+        expression: &str,
+        id: Option<usize>,
+        context: EvaluateContext,
+    ) -> Result<Variable, String> {
+        if !context.allows_code() && !is_attribute_path(expression) {
+            return Err(format!("{} is not a variable", expression.trim()));
+        }
+        let Some(index) = id.or_else(|| self.frames.len().checked_sub(1)) else {
+            return Err("the program has no frames".to_string());
+        };
+        if !context.allows_code() {
+            return self.hover(expression.trim(), index);
+        }
+
+        let value = self.evaluate_in_frame(expression, Some(index))?;
+        let mut variable = self.variable(expression.trim().to_string(), &value);
+        variable.value = render(
+            &evaluated(&value),
+            RenderOptions::default(),
+            Some(&self.code),
+        );
+        Ok(variable)
+    }
+
+    /// Evaluate an expression of the user in frame `index`, or without a
+    /// scope if unset. Forcing a thunk of the program can fail in a way
+    /// that leaves it unusable, like in `force`, the user is told about
+    /// those
+    fn evaluate_in_frame(
+        &mut self,
+        expression: &str,
+        index: Option<usize>,
+    ) -> Result<Value, String> {
+        let scope = match index {
+            Some(index) => self.expression_scope(index),
+            None => FxHashMap::default(),
+        };
+        let forceable = forceable_thunks(expression, &scope);
+        let result = evaluate_expression(expression, &scope, &self.code);
+        if let Err(error) = &result {
+            for (name, thunk) in forceable {
+                // a thunk that is neither suspended nor evaluated is
+                // blackholed for good
+                if !thunk.is_suspended() && !thunk.is_evaluated() {
+                    self.warn_unusable(&name, error);
                 }
             }
         }
-        for val in &self.cur_state.stack {
-            match val {
-                // TODO: calling val on suspended thunks results in error
-                Value::Thunk(_) => continue,
-                Value::Closure(closure) => {
-                    // println!("looking at: {}", val.explain());
-                    if let Some(fn_name) = &closure.lambda.name {
-                        if *fn_name == var_name {
-                            // println!("FOUND IT {}", val.explain());
-                        }
-                    }
-                }
-                // _ => println!("looking at: {}", val.explain()),
-                _ => continue,
+        result
+    }
+
+    /// The bindings an expression of the user sees in frame `index`, the
+    /// builtins come from its own evaluation
+    fn expression_scope(&mut self, index: usize) -> FxHashMap<SmolStr, Value> {
+        let mut scope = FxHashMap::default();
+        // outer scopes go in first, the inner ones shadow them
+        for (_, kind, bindings) in self.scope_bindings(index).into_iter().rev() {
+            if kind != ScopeKind::Builtins {
+                scope.extend(bindings);
             }
         }
+        scope
+    }
+
+    /// Override the variable `name` of the container behind `reference`
+    /// with the value of `expression`, evaluated in the top frame
+    fn set_variable(
+        &mut self,
+        reference: usize,
+        name: &str,
+        expression: &str,
+    ) -> Result<Variable, String> {
+        let entries = match self.variables.get(reference).cloned() {
+            Some(Container::Bindings(bindings)) => bindings
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), value))
+                .collect(),
+            Some(Container::Value(value)) => children(&value),
+            Some(Container::Force { .. }) => vec![],
+            None => return Err(format!("no variables for reference {}", reference)),
+        };
+        let target = entries
+            .into_iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| value)
+            .ok_or_else(|| format!("no variable {}", name))?;
+        let index = self.frames.len().checked_sub(1);
+        self.override_value(name, &target, expression, index)
+    }
 
-        // println!("looking for {}", var_name);
+    /// Override the attribute path `path` as seen from frame `id` (the top
+    /// one if unset) with the value of `expression`, evaluated there
+    fn set_expression(
+        &mut self,
+        path: &str,
+        expression: &str,
+        id: Option<usize>,
+    ) -> Result<Variable, String> {
+        let path = path.trim();
+        if !is_attribute_path(path) {
+            return Err(format!("{} is not a variable", path));
+        }
+        let Some(index) = id.or_else(|| self.frames.len().checked_sub(1)) else {
+            return Err("the program has no frames".to_string());
+        };
+        match self.resolve_path(path, index)? {
+            Resolved::Value(target) => self.override_value(path, &target, expression, Some(index)),
+            Resolved::Unforced(walked) => Err(format!("{} is not evaluated yet", walked)),
+        }
     }
 
-    fn handle_break(&mut self, breakpoint: Breakpoint) {
-        let line = match breakpoint {
-            Breakpoint::Line(line) => line,
-            Breakpoint::FileLine { file: _, line } => line,
+    /// Replace `target`, a thunk nobody forced yet, by the value of
+    /// `expression`. Values are immutable once forced, the program may
+    /// already depend on them
+    fn override_value(
+        &mut self,
+        name: &str,
+        target: &Value,
+        expression: &str,
+        index: Option<usize>,
+    ) -> Result<Variable, String> {
+        let thunk = match target {
+            Value::Thunk(thunk) if thunk.is_suspended() => thunk,
+            _ => {
+                return Err(format!(
+                    "{} is already evaluated, only unforced values can be overridden",
+                    name
+                ))
+            }
         };
+        let value = self.evaluate_in_frame(expression, index)?;
+        override_thunk(thunk, evaluated(&value));
+        self.overrides.push(thunk.clone());
+        Ok(self.variable(name.to_string(), target))
+    }
 
-        let _ = self.breakpoints.breakpoints.insert(line);
+    /// The names completing the word of `text` before `cursor`, from the
+    /// scopes of frame `id` (the top one if unset)
+    fn completions(&mut self, text: &str, cursor: usize, id: Option<usize>) -> Vec<Completion> {
+        let Some(index) = id.or_else(|| self.frames.len().checked_sub(1)) else {
+            return vec![];
+        };
+        complete(text, cursor, &self.scope_bindings(index))
+    }
+
+    /// The value of the attribute path `path` as seen from frame `index`,
+    /// without running any code. Hovers fire all the time, forcing a thunk
+    /// there would change what the program evaluates and in which order
+    fn hover(&mut self, path: &str, index: usize) -> Result<Variable, String> {
+        match self.resolve_path(path, index)? {
+            Resolved::Value(value) if !is_unforced(&value) => {
+                let mut variable = self.variable(path.to_string(), &value);
+                variable.value = render(&value, RenderOptions::default(), Some(&self.code));
+                Ok(variable)
+            }
+            _ => Ok(Variable {
+                name: path.to_string(),
+                value: "<not yet evaluated>".to_string(),
+                type_name: None,
+                variables_reference: 0,
+                named_variables: None,
+                indexed_variables: None,
+                lazy: false,
+                overridden: false,
+            }),
+        }
+    }
+
+    /// Look up the attribute path `path` (`a.b.c`) in the scopes of frame
+    /// `index`, walking through forced sets only
+    fn resolve_path(&mut self, path: &str, index: usize) -> Result<Resolved, String> {
+        let mut names = path.split('.').map(str::trim);
+        let first = names.next().unwrap_or_default();
+        let mut value = self
+            .scope_bindings(index)
+            .into_iter()
+            .find_map(|(_, _, bindings)| {
+                bindings
+                    .into_iter()
+                    .find(|(name, _)| name == first)
+                    .map(|(_, value)| value)
+            })
+            .ok_or_else(|| format!("{} is not in scope", first))?;
+
+        let mut walked = first.to_string();
+        for name in names {
+            if is_unforced(&value) {
+                return Ok(Resolved::Unforced(walked));
+            }
+            value = attrs_bindings(&value)
+                .and_then(|bindings| bindings.into_iter().find(|(n, _)| n == name))
+                .map(|(_, value)| value)
+                .ok_or_else(|| format!("{} has no attribute {}", walked, name))?;
+            walked = format!("{}.{}", walked, name);
+        }
+        Ok(Resolved::Value(evaluated(&value)))
+    }
+
+    /// Render the variable `path` (`a.b.c` for attributes of already
+    /// forced sets) as seen from the top frame
+    fn handle_print(&mut self, path: SerSmolStr) -> Result<String, String> {
+        let Some(index) = self.frames.len().checked_sub(1) else {
+            return Err("the program has no frames".to_string());
+        };
+        match self.resolve_path(&path, index)? {
+            Resolved::Value(value) => {
+                Ok(render(&value, RenderOptions::default(), Some(&self.code)))
+            }
+            Resolved::Unforced(walked) => Err(format!("{} is not evaluated yet", walked)),
+        }
     }
 }
 
 impl RuntimeObserver for DebugObserver {
     fn observe_enter_call_frame(
         &mut self,
-        _arg_count: usize,
+        arg_count: usize,
         lambda: &std::rc::Rc<Lambda>,
-        _call_depth: usize,
+        call_depth: usize,
     ) {
-        // println!(
-        //     "entering call frame: {}",
-        //     (lambda.name.clone()).unwrap_or("hello".into())
-        // );
+        match self.frames.last_mut() {
+            Some(frame)
+                if frame.suspended
+                    && frame.lambda.as_ref().is_some_and(|l| Rc::ptr_eq(l, lambda)) =>
+            {
+                frame.suspended = false;
+            }
+            _ => {
+                let upvalues = self
+                    .callee
+                    .take()
+                    .and_then(|callee| callee.enter(lambda, arg_count));
+                let kind = if arg_count > 0 {
+                    FrameKind::Lambda
+                } else if self.frames.iter().any(|frame| frame.kind.is_code()) {
+                    FrameKind::Thunk
+                } else {
+                    FrameKind::Toplevel
+                };
+                let mut frame = Frame::call(kind, lambda, arg_count, call_depth);
+                frame.upvalues = upvalues;
+                self.frames.push(frame);
+                self.entered_function = lambda.name.clone();
+            }
+        }
         self.cur_state.lambda = Some(lambda.to_owned());
+        self.last_location = None;
     }
 
     fn observe_exit_call_frame(&mut self, _frame_at: usize, stack: &[tvix_eval::Value]) {
-        // println!("{}", self.cur_state);
+        self.pop_frame(|kind| kind.is_code());
         self.cur_state.stack = stack.to_owned();
     }
 
-    fn observe_suspend_call_frame(&mut self, _frame_at: usize, _stack: &[tvix_eval::Value]) {}
+    fn observe_suspend_call_frame(&mut self, _frame_at: usize, _stack: &[tvix_eval::Value]) {
+        if let Some(frame) = self
+            .frames
+            .iter_mut()
+            .rev()
+            .find(|frame| frame.kind.is_code())
+        {
+            frame.suspended = true;
+        }
+    }
 
-    fn observe_enter_generator(
-        &mut self,
-        _frame_at: usize,
-        _name: &str,
-        stack: &[tvix_eval::Value],
-    ) {
+    fn observe_enter_generator(&mut self, frame_at: usize, name: &str, stack: &[tvix_eval::Value]) {
+        // generators are entered again each time they resume
+        match self.frames.last_mut() {
+            Some(frame) if frame.suspended && frame.frame_at == Some(frame_at) => {
+                frame.suspended = false;
+            }
+            _ => self.frames.push(Frame::native(
+                FrameKind::Generator,
+                name.into(),
+                Some(frame_at),
+            )),
+        }
         self.cur_state.stack = stack.to_owned();
     }
 
-    fn observe_exit_generator(
-        &mut self,
-        _frame_at: usize,
-        _name: &str,
-        stack: &[tvix_eval::Value],
-    ) {
+    fn observe_exit_generator(&mut self, frame_at: usize, _name: &str, stack: &[tvix_eval::Value]) {
+        if let Some(index) = self
+            .frames
+            .iter()
+            .rposition(|frame| frame.frame_at == Some(frame_at))
+        {
+            self.frames.truncate(index);
+        }
         self.cur_state.stack = stack.to_owned();
     }
 
     fn observe_suspend_generator(
         &mut self,
-        _frame_at: usize,
+        frame_at: usize,
         _name: &str,
         stack: &[tvix_eval::Value],
     ) {
+        if let Some(frame) = self
+            .frames
+            .iter_mut()
+            .rev()
+            .find(|frame| frame.frame_at == Some(frame_at))
+        {
+            frame.suspended = true;
+        }
         self.cur_state.stack = stack.to_owned();
     }
 
-    fn observe_generator_request(&mut self, _name: &str, _msg: &tvix_eval::generators::VMRequest) {}
+    fn observe_generator_request(&mut self, _name: &str, msg: &tvix_eval::generators::VMRequest) {
+        // builtins call and force through the VM, e.g. `map` calling `f`
+        use tvix_eval::generators::VMRequest;
+        if let VMRequest::Call(value) | VMRequest::ForceValue(value) = msg {
+            self.callee = Callee::of(value);
+        }
+    }
 
-    fn observe_tail_call(&mut self, _frame_at: usize, _: &std::rc::Rc<Lambda>) {}
+    fn observe_tail_call(&mut self, _frame_at: usize, lambda: &std::rc::Rc<Lambda>) {
+        // the called lambda takes over the frame, with its argument
+        if let Some(frame) = self.frames.last_mut().filter(|frame| frame.kind.is_code()) {
+            let call_depth = frame.call_depth;
+            *frame = Frame::call(FrameKind::Lambda, lambda, 1, call_depth);
+            frame.upvalues = self
+                .callee
+                .take()
+                .and_then(|callee| callee.enter(lambda, 1));
+        }
+        // entered like any call, function breakpoints see it at its first op
+        self.entered_function = lambda.name.clone();
+        self.last_location = None;
+    }
 
-    fn observe_enter_builtin(&mut self, _name: &'static str) {}
+    fn observe_enter_builtin(&mut self, name: &'static str) {
+        if name == "tryEval" {
+            self.try_depth += 1;
+        }
+        self.frames.push(Frame::native(
+            FrameKind::Builtin,
+            format!("builtins.{}", name).into(),
+            None,
+        ));
+        // builtins called by other builtins, e.g. by `map`, have no op
+        // calling them
+        let exception = !std::mem::take(&mut self.raising)
+            && builtin_exception(name)
+                .is_some_and(|kind| self.is_exception(kind, format!("called builtins.{}", name)));
+        if exception
+            || self.is_running()
+                && self.is_function_breakpoint(&format!("builtins.{}", name), None, &[])
+        {
+            self.pause(None, &[]);
+        }
+    }
 
-    fn observe_exit_builtin(&mut self, _name: &'static str, _stack: &[tvix_eval::Value]) {}
+    fn observe_exit_builtin(&mut self, name: &'static str, _stack: &[tvix_eval::Value]) {
+        if name == "tryEval" {
+            self.try_depth = self.try_depth.saturating_sub(1);
+        }
+        self.pop_frame(|kind| kind == FrameKind::Builtin);
+    }
 
-    fn observe_execute_op(&mut self, span: &Span, _ip: CodeIdx, op: &Op, _: &[tvix_eval::Value]) {
-        let pause = if self.cur_cmd != ObserverCommand::Continue || self.is_breakpoint(span) {
-            true
-        } else {
-            false
+    /// Breaking ends the evaluation with an error before `op` runs, which is
+    /// how a terminated program leaves the VM
+    fn observe_execute_op(
+        &mut self,
+        span: &Span,
+        _ip: CodeIdx,
+        op: &Op,
+        stack: &[tvix_eval::Value],
+    ) -> ControlFlow<()> {
+        if self.terminated {
+            return ControlFlow::Break(());
+        }
+        if let Some(frame) = self.frames.last_mut() {
+            if frame.stack_offset.is_none() {
+                frame.stack_offset = Some(stack.len().saturating_sub(frame.arg_count));
+            }
+            frame.span = Some(*span);
+        }
+        self.callee = match (op, stack.last()) {
+            (Op::Call | Op::Force, Some(value)) => Callee::of(value),
+            _ => None,
         };
 
-        if self.cur_cmd == ObserverCommand::Step {
-            // let snippet = self.code.source_slice(*span);
-            // let line = self.code.get_line(*span);
-            //
-            // println!("snippet: {}, on line {}", snippet, line);
-            let name = if let Some(lambda) = &self.cur_state.lambda {
-                lambda.name.clone().unwrap_or("anonymous".into())
-            } else {
-                "anonymous lambda".into()
-            };
-            println!("Lambda: {}, Cur_op: {:?}", name, op);
+        // a single load per op, the flag is only set by a pause request
+        if self.interrupt.load(Ordering::Relaxed) {
+            self.interrupt.store(false, Ordering::Relaxed);
+            // commands are handled while paused, except for the ones ending
+            // the session, which interrupt the program
+            while let Ok(command) = self.receiver.try_recv() {
+                match command {
+                    ObserverCommand::Exit => self.terminate(),
+                    ObserverCommand::Detach => self.detach(),
+                    command => self.pending.push_back(command),
+                }
+            }
+            if self.terminated {
+                return ControlFlow::Break(());
+            }
+            if self.is_running() && !self.detached {
+                self.stop_reason = Some(StopReason::Pause);
+                self.cur_cmd = ObserverCommand::Step;
+            }
+        }
+
+        if let Some(name) = self.entered_function.take() {
+            if self.is_running() {
+                // stops by switching to stepping
+                self.is_function_breakpoint(&name, Some(span), stack);
+            }
+        }
+        if self.exceptions_changed.swap(false, Ordering::Relaxed) {
+            self.exception_filters = self.breakpoints.lock().unwrap().exception_filters().clone();
+        }
+        self.raising = false;
+        if !self.exception_filters.kinds.is_empty() {
+            if let Some((kind, description)) = op_exception(op, stack) {
+                // throw and abort are seen here, with the frame of the caller
+                self.raising = matches!(op, Op::Call)
+                    && matches!(kind, ExceptionKind::Throw | ExceptionKind::Abort);
+                self.is_exception(kind, description);
+            }
+        }
+
+        if self.stop_on_entry && self.locate(span).file.is_file() {
+            self.stop_on_entry = false;
+            self.stop_reason = Some(StopReason::Entry);
+            self.cur_cmd = ObserverCommand::Step;
         }
-        if pause {
-            let _ = self.handle_command();
+
+        let pause = match self.cur_cmd {
+            ObserverCommand::Continue => self.is_breakpoint(span, stack),
+            ObserverCommand::Next(_) | ObserverCommand::StepIn(_) | ObserverCommand::StepOut => {
+                // evaluated first, to keep track of the position
+                let breakpoint = self.is_breakpoint(span, stack);
+                self.step_finished(span) || breakpoint
+            }
+            _ => true,
+        };
+
+        if self.cur_cmd == ObserverCommand::Wait {
+            // not launched yet, there is nothing to report
+            if self.handle_command().is_err() {
+                self.terminate();
+            }
+        } else if pause {
+            self.pause(Some(span), stack);
+        }
+
+        if self.terminated {
+            ControlFlow::Break(())
+        } else {
+            ControlFlow::Continue(())
         }
     }
 }
@@ -0,0 +1,293 @@
+use std::fmt::Write;
+
+use tvix_eval::{value::Thunk, SourceCode, Value};
+
+use crate::variables::evaluated;
+
+/// How much of a value is rendered, what is cut is replaced by `…`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RenderOptions {
+    /// nesting of sets and lists shown, deeper ones are `{ … }` and `[ … ]`
+    pub max_depth: usize,
+    /// attributes or elements shown per set or list
+    pub max_width: usize,
+    /// characters shown per string
+    pub max_string: usize,
+}
+
+impl RenderOptions {
+    /// One line summaries, e.g. the value of a variable that can be expanded
+    pub const SUMMARY: RenderOptions = RenderOptions {
+        max_depth: 1,
+        max_width: 5,
+        max_string: 80,
+    };
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        RenderOptions {
+            max_depth: 4,
+            max_width: 50,
+            max_string: 1000,
+        }
+    }
+}
+
+/// Render `value` in nix syntax for the user, without forcing anything.
+/// Values nix can't write down are shown in `«»`. With the source map of the
+/// program, lambdas show their formals as written
+pub fn render(value: &Value, options: RenderOptions, code: Option<&SourceCode>) -> String {
+    let mut renderer = Renderer {
+        options,
+        code,
+        thunks: vec![],
+        out: String::new(),
+    };
+    renderer.value(value, 0);
+    renderer.out
+}
+
+struct Renderer<'a> {
+    options: RenderOptions,
+    code: Option<&'a SourceCode>,
+    /// thunks whose values are being rendered, seeing one again is a cycle
+    thunks: Vec<Thunk>,
+    out: String,
+}
+
+impl Renderer<'_> {
+    fn value(&mut self, value: &Value, depth: usize) {
+        if let Value::Thunk(thunk) = value {
+            if self.thunks.iter().any(|seen| seen.ptr_eq(thunk)) {
+                self.out.push_str("«cycle»");
+                return;
+            }
+            if thunk.is_evaluated() {
+                self.thunks.push(thunk.clone());
+                self.value(&thunk.value(), depth);
+                self.thunks.pop();
+                return;
+            }
+        }
+
+        match value {
+            Value::Thunk(thunk) if thunk.is_suspended() => self.out.push_str("«thunk»"),
+            Value::Thunk(_) => self.out.push_str("«thunk being evaluated»"),
+            Value::String(s) => self.string(&String::from_utf8_lossy(s.as_bytes())),
+            Value::Attrs(attrs) => {
+                if let Some(summary) = derivation(value) {
+                    self.out.push_str(&summary);
+                    return;
+                }
+                if attrs.is_empty() {
+                    self.out.push_str("{ }");
+                    return;
+                }
+                if depth >= self.options.max_depth {
+                    self.out.push_str("{ … }");
+                    return;
+                }
+                self.out.push('{');
+                for (name, value) in attrs.iter().take(self.options.max_width) {
+                    let _ = write!(self.out, " {} = ", attr_name(name.as_bytes()));
+                    self.value(value, depth + 1);
+                    self.out.push(';');
+                }
+                self.more(attrs.len());
+                self.out.push_str(" }");
+            }
+            Value::List(list) => {
+                if list.is_empty() {
+                    self.out.push_str("[ ]");
+                    return;
+                }
+                if depth >= self.options.max_depth {
+                    self.out.push_str("[ … ]");
+                    return;
+                }
+                self.out.push('[');
+                for value in list.iter().take(self.options.max_width) {
+                    self.out.push(' ');
+                    self.value(value, depth + 1);
+                }
+                self.more(list.len());
+                self.out.push_str(" ]");
+            }
+            Value::Closure(closure) => {
+                let lambda = &closure.lambda;
+                self.out.push_str("«lambda");
+                if let Some(name) = &lambda.name {
+                    let _ = write!(self.out, " {}", name);
+                }
+                if let Some(formals) = &lambda.formals {
+                    let written = self.code.map(|code| {
+                        let codemap = code.codemap();
+                        let file = codemap.find_file(formals.span.low());
+                        file.source_slice(formals.span)
+                            .split_whitespace()
+                            .collect::<Vec<_>>()
+                            .join(" ")
+                    });
+                    let formals = written.unwrap_or_else(|| {
+                        // without the source, defaults are only known to exist
+                        let mut arguments: Vec<String> = formals
+                            .arguments
+                            .iter()
+                            .map(|(name, has_default)| {
+                                let name = attr_name(name.as_bytes());
+                                if *has_default {
+                                    format!("{} ? …", name)
+                                } else {
+                                    name
+                                }
+                            })
+                            .collect();
+                        if formals.ellipsis {
+                            arguments.push("...".to_string());
+                        }
+                        format!("{{ {} }}", arguments.join(", "))
+                    });
+                    let _ = write!(self.out, " {}", formals);
+                }
+                self.out.push('»');
+            }
+            Value::Builtin(builtin) => {
+                let _ = write!(self.out, "«builtin {}»", builtin.name());
+            }
+            Value::Catchable(error) => {
+                let _ = write!(self.out, "«error: {}»", error);
+            }
+            other => {
+                let _ = write!(self.out, "{}", other);
+            }
+        }
+    }
+
+    /// A quoted string, escaped like nix does, cut after `max_string` chars
+    fn string(&mut self, s: &str) {
+        self.out.push('"');
+        let mut chars = s.chars().peekable();
+        let mut shown = 0;
+        while let Some(c) = chars.next() {
+            if shown == self.options.max_string {
+                self.out.push('…');
+                break;
+            }
+            shown += 1;
+            match c {
+                '"' => self.out.push_str("\\\""),
+                '\\' => self.out.push_str("\\\\"),
+                '\n' => self.out.push_str("\\n"),
+                '\r' => self.out.push_str("\\r"),
+                '\t' => self.out.push_str("\\t"),
+                '$' if chars.peek() == Some(&'{') => self.out.push_str("\\$"),
+                c => self.out.push(c),
+            }
+        }
+        self.out.push('"');
+    }
+
+    /// How many items of a set or list of `len` were left out
+    fn more(&mut self, len: usize) {
+        if len > self.options.max_width {
+            let _ = write!(self.out, " …{} more", len - self.options.max_width);
+        }
+    }
+}
+
+/// An attribute name, quoted unless it is a valid identifier
+fn attr_name(name: &[u8]) -> String {
+    let name = String::from_utf8_lossy(name);
+    let mut chars = name.chars();
+    let identifier = chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || "_'-".contains(c));
+    if identifier {
+        name.into_owned()
+    } else {
+        format!("{:?}", name)
+    }
+}
+
+/// Summary of a derivation: its name, then the drv and out paths as far as
+/// they are already known
+fn derivation(value: &Value) -> Option<String> {
+    let Value::Attrs(attrs) = value else {
+        return None;
+    };
+    let forced_string = |name: &str| match attrs.select(name).map(evaluated) {
+        Some(Value::String(s)) => Some(String::from_utf8_lossy(s.as_bytes()).into_owned()),
+        _ => None,
+    };
+    if forced_string("type").as_deref() != Some("derivation") {
+        return None;
+    }
+
+    let mut summary = String::from("«derivation");
+    for (label, attr) in [("", "name"), ("drv=", "drvPath"), ("out=", "outPath")] {
+        if let Some(value) = forced_string(attr) {
+            let _ = write!(summary, " {}{}", label, value);
+        }
+    }
+    summary.push('»');
+    Some(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use tvix_eval::{EvalMode, Evaluation, SourceCode};
+
+    use super::*;
+
+    fn rendered(code: &str, options: RenderOptions) -> String {
+        let source = SourceCode::default();
+        let result = Evaluation::builder_pure()
+            .mode(EvalMode::Strict)
+            .with_source_map(source.clone())
+            .build()
+            .evaluate(code, None);
+        render(&result.value.unwrap(), options, Some(&source))
+    }
+
+    #[test]
+    fn renders_nix_syntax() {
+        let options = RenderOptions::default();
+        assert_eq!(
+            rendered(
+                r#"{ a = 1; "b c" = "x\n${"$"}{y}"; d = [ 1.5 true null ]; }"#,
+                options
+            ),
+            r#"{ a = 1; "b c" = "x\n\${y}"; d = [ 1.5 true null ]; }"#
+        );
+        assert_eq!(
+            rendered("{ a, b ? 2, ... }: a", options),
+            "«lambda { a, b ? 2, ... }»"
+        );
+    }
+
+    #[test]
+    fn respects_limits() {
+        let options = RenderOptions {
+            max_depth: 2,
+            max_width: 3,
+            max_string: 4,
+        };
+        assert_eq!(
+            rendered("{ a = { b = { c = 1; }; }; }", options),
+            "{ a = { b = { … }; }; }"
+        );
+        assert_eq!(rendered("[ 1 2 3 4 5 ]", options), "[ 1 2 3 …2 more ]");
+        assert_eq!(rendered(r#""abcdef""#, options), r#""abcd…""#);
+    }
+
+    #[test]
+    fn summarizes_derivations() {
+        let drv = r#"{ type = "derivation"; name = "hello"; outPath = "/nix/store/x-hello"; }"#;
+        assert_eq!(
+            rendered(drv, RenderOptions::default()),
+            "«derivation hello out=/nix/store/x-hello»"
+        );
+    }
+}
@@ -0,0 +1,370 @@
+use std::collections::HashMap;
+
+use codemap::Span;
+use rnix::{
+    ast::{self, AstNode, HasEntry},
+    SyntaxNode, SyntaxToken,
+};
+use rowan::TextSize;
+use smol_str::SmolStr;
+use tvix_eval::SourceCode;
+
+/// What the compiler keeps in a local slot of a frame
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Slot {
+    /// an argument or a `let` binding
+    Local(SmolStr),
+    /// the namespace of a `with`, by its source text
+    With(String),
+    /// a slot the compiler doesn't name, e.g. an argument set without `@`
+    Hidden,
+}
+
+/// The runtime only knows about stack slots, so the names of the locals of a
+/// frame are recovered from the syntax tree, declaring them in the same order
+/// as the tvix compiler does
+#[derive(Default)]
+pub struct Syntax {
+    /// parsed files, by their name in the source map
+    files: HashMap<String, ast::Root>,
+}
+
+impl Syntax {
+    /// The local slots (relative to the frame's stack offset) of the frame
+    /// executing `span`. `lambda_frame` tells if the frame is a called
+    /// lambda, as opposed to a forced thunk or the toplevel
+    pub fn slots(&mut self, code: &SourceCode, span: Span, lambda_frame: bool) -> Vec<Slot> {
+        let Some(token) = self.token_at(code, span) else {
+            return vec![];
+        };
+
+        // from the node the frame was compiled for, down to the current one
+        let mut path: Vec<SyntaxNode> = vec![];
+        for node in token.parent_ancestors() {
+            let is_frame = if lambda_frame {
+                ast::Lambda::can_cast(node.kind())
+            } else {
+                is_thunk(&node) || ast::Lambda::can_cast(node.kind())
+            };
+            path.push(node);
+            if is_frame {
+                break;
+            }
+        }
+        path.reverse();
+
+        let mut slots = vec![];
+        if let Some(lambda) = path.first().cloned().and_then(ast::Lambda::cast) {
+            slots.extend(param_slots(&lambda));
+        }
+
+        for pair in path.windows(2) {
+            let (node, child) = (&pair[0], &pair[1]);
+            if let Some(let_in) = ast::LetIn::cast(node.clone()) {
+                if let_in.body().is_some_and(|body| body.syntax() == child) {
+                    slots.extend(binding_names(&let_in).into_iter().map(Slot::Local));
+                }
+            } else if let Some(with) = ast::With::cast(node.clone()) {
+                // the namespace of a `with` is kept in a phantom slot
+                if with.body().is_some_and(|body| body.syntax() == child) {
+                    slots.push(Slot::With(namespace_text(&with)));
+                }
+            }
+        }
+
+        slots
+    }
+
+    /// Source text of the namespaces of the `with`s around the code of the
+    /// frame executing `span`, outermost first like the with stack its
+    /// closure or thunk captured
+    pub fn withs(&mut self, code: &SourceCode, span: Span, lambda_frame: bool) -> Vec<String> {
+        let frame = self.token_at(code, span).and_then(|token| {
+            token
+                .parent_ancestors()
+                .find(|node| ast::Lambda::can_cast(node.kind()) || !lambda_frame && is_thunk(node))
+        });
+        frame
+            .map(|frame| captured_withs(&frame))
+            .unwrap_or_default()
+    }
+
+    /// The attribute the thunk executing `span` is the value of, by its
+    /// attribute path, e.g. `meta.description`
+    pub fn thunk_attribute(&mut self, code: &SourceCode, span: Span) -> Option<String> {
+        let thunk = self
+            .token_at(code, span)?
+            .parent_ancestors()
+            .find(|node| is_thunk(node) || ast::Lambda::can_cast(node.kind()))?;
+        let binding = ast::AttrpathValue::cast(thunk.parent()?)?;
+        Some(binding.attrpath()?.syntax().text().to_string())
+    }
+
+    /// The token `span` starts at, in the syntax tree of its file
+    fn token_at(&mut self, code: &SourceCode, span: Span) -> Option<SyntaxToken> {
+        let (root, offset) = {
+            let codemap = code.codemap();
+            let file = codemap.find_file(span.low());
+            let root = self
+                .files
+                .entry(file.name().to_string())
+                .or_insert_with(|| rnix::Root::parse(file.source()).tree())
+                .clone();
+            (root, span.low() - file.span.low())
+        };
+
+        root.syntax()
+            .token_at_offset(TextSize::from(offset as u32))
+            .right_biased()
+    }
+}
+
+/// The namespaces of the `with`s enclosing `frame`, see `Syntax::withs`
+fn captured_withs(frame: &SyntaxNode) -> Vec<String> {
+    let mut withs: Vec<String> = frame
+        .ancestors()
+        .skip(1)
+        .filter_map(ast::With::cast)
+        .map(|with| namespace_text(&with))
+        .collect();
+    withs.reverse();
+    withs
+}
+
+fn namespace_text(with: &ast::With) -> String {
+    with.namespace()
+        .map(|namespace| namespace.syntax().text().to_string())
+        .unwrap_or_default()
+}
+
+/// Whether the compiler puts `node` into a thunk of its own
+fn is_thunk(node: &SyntaxNode) -> bool {
+    let Some(parent) = node.parent() else {
+        return false;
+    };
+
+    if let Some(apply) = ast::Apply::cast(parent.clone()) {
+        return apply.argument().is_some_and(|arg| arg.syntax() == node);
+    }
+    if let Some(binding) = ast::AttrpathValue::cast(parent.clone()) {
+        return binding.value().is_some_and(|value| value.syntax() == node);
+    }
+    ast::List::can_cast(parent.kind())
+}
+
+/// Slots holding the argument of a lambda: the identifier, or for a pattern
+/// the whole set (named if bound with `@`) followed by every formal
+fn param_slots(lambda: &ast::Lambda) -> Vec<Slot> {
+    let slot = |name: Option<SmolStr>| name.map_or(Slot::Hidden, Slot::Local);
+    match lambda.param() {
+        Some(ast::Param::IdentParam(param)) => {
+            vec![slot(param.ident().and_then(|i| ident_name(&i)))]
+        }
+        Some(ast::Param::Pattern(pattern)) => {
+            let set = pattern
+                .pat_bind()
+                .and_then(|bind| bind.ident())
+                .and_then(|i| ident_name(&i));
+            std::iter::once(slot(set))
+                .chain(
+                    pattern
+                        .pat_entries()
+                        .map(|entry| slot(entry.ident().and_then(|i| ident_name(&i)))),
+                )
+                .collect()
+        }
+        None => vec![],
+    }
+}
+
+/// Whether `expression` is a variable or a selection of attributes from
+/// one, `a.b.c`, which can be evaluated without running any code
+pub fn is_attribute_path(expression: &str) -> bool {
+    let parse = rnix::Root::parse(expression);
+    if !parse.errors().is_empty() {
+        return false;
+    }
+    let mut expr = parse.tree().expr();
+    loop {
+        match expr {
+            Some(ast::Expr::Ident(_)) => return true,
+            Some(ast::Expr::Select(select)) => {
+                let plain = select.default_expr().is_none()
+                    && select
+                        .attrpath()
+                        .is_some_and(|path| path.attrs().all(|a| matches!(a, ast::Attr::Ident(_))));
+                if !plain {
+                    return false;
+                }
+                expr = select.expr();
+            }
+            _ => return false,
+        }
+    }
+}
+
+/// The variables `expression` takes from the scope it is evaluated in, each
+/// with the plain attributes selected from it: `["a", "b"]` for `a.b + 1`.
+/// Names the expression binds itself are left out
+pub fn free_paths(expression: &str) -> Vec<Vec<SmolStr>> {
+    let root = rnix::Root::parse(expression).tree();
+    let mut paths = vec![];
+    for node in root.syntax().descendants() {
+        let Some(name) = variable_reference(&node) else {
+            continue;
+        };
+        if node
+            .ancestors()
+            .any(|ancestor| binds(&ancestor, &node, &name))
+        {
+            continue;
+        }
+        let mut path = vec![name];
+        let select = node
+            .parent()
+            .and_then(ast::Select::cast)
+            .filter(|select| select.expr().is_some_and(|expr| expr.syntax() == &node));
+        if let Some(attrpath) = select.and_then(|select| select.attrpath()) {
+            path.extend(attrpath.attrs().map_while(|attr| attr_name(&attr)));
+        }
+        paths.push(path);
+    }
+    paths
+}
+
+/// The variable `node` refers to, if it is an identifier used as a value
+/// (or inherited from the enclosing scope)
+fn variable_reference(node: &SyntaxNode) -> Option<SmolStr> {
+    let ident = ast::Ident::cast(node.clone())?;
+    let parent = node.parent()?;
+    if let Some(inherit) = ast::Inherit::cast(parent.clone()) {
+        return inherit
+            .from()
+            .is_none()
+            .then(|| ident_name(&ident))
+            .flatten();
+    }
+    // the default of a formal is a value, its name is not
+    let formal = ast::PatEntry::cast(parent.clone())
+        .and_then(|entry| entry.ident())
+        .is_some_and(|formal| formal.syntax() == node);
+    let declares = formal
+        || ast::Attrpath::can_cast(parent.kind())
+        || ast::IdentParam::can_cast(parent.kind())
+        || ast::PatBind::can_cast(parent.kind());
+    if declares {
+        return None;
+    }
+    ident_name(&ident)
+}
+
+/// Whether `binder` declares `name` in the scope of `node`, one of its
+/// descendants
+fn binds(binder: &SyntaxNode, node: &SyntaxNode, name: &SmolStr) -> bool {
+    if let Some(lambda) = ast::Lambda::cast(binder.clone()) {
+        // the defaults of formals see the other formals
+        return param_slots(&lambda).contains(&Slot::Local(name.clone()));
+    }
+    if let Some(let_in) = ast::LetIn::cast(binder.clone()) {
+        // `inherit x;` in a let refers to the outer x
+        let inherited = node
+            .parent()
+            .is_some_and(|parent| ast::Inherit::can_cast(parent.kind()));
+        return !inherited && binding_names(&let_in).contains(name);
+    }
+    if let Some(set) = ast::AttrSet::cast(binder.clone()) {
+        return set.rec_token().is_some() && binding_names(&set).contains(name);
+    }
+    false
+}
+
+/// Names bound by a `let` (or recursive attribute set): plain inherits,
+/// then namespaced inherits, then the bindings themselves
+pub fn binding_names(node: &impl HasEntry) -> Vec<SmolStr> {
+    let mut names: Vec<SmolStr> = vec![];
+    let plain = node.inherits().filter(|inherit| inherit.from().is_none());
+    let namespaced = node.inherits().filter(|inherit| inherit.from().is_some());
+    for inherit in plain.chain(namespaced) {
+        names.extend(inherit.attrs().filter_map(|attr| attr_name(&attr)));
+    }
+
+    for binding in node.attrpath_values() {
+        let name = binding
+            .attrpath()
+            .and_then(|path| path.attrs().next())
+            .and_then(|attr| attr_name(&attr));
+        if let Some(name) = name {
+            // `a.b = ..; a.c = ..;` only declares `a` once
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+    }
+
+    names
+}
+
+fn attr_name(attr: &ast::Attr) -> Option<SmolStr> {
+    match attr {
+        ast::Attr::Ident(ident) => ident_name(ident),
+        _ => None,
+    }
+}
+
+fn ident_name(ident: &ast::Ident) -> Option<SmolStr> {
+    ident.ident_token().map(|token| token.text().into())
+}
+
+#[cfg(test)]
+mod tests {
+    use rnix::ast::{self, AstNode};
+
+    use super::{binding_names, captured_withs, free_paths, is_attribute_path};
+
+    #[test]
+    fn let_bindings_are_declared_in_compiler_order() {
+        let root =
+            rnix::Root::parse("let inherit (x) y; inherit z; a.b = 1; a.c = 2; d = 3; in d").tree();
+        let let_in = root
+            .syntax()
+            .descendants()
+            .find_map(ast::LetIn::cast)
+            .unwrap();
+
+        assert_eq!(binding_names(&let_in), vec!["z", "y", "a", "d"]);
+    }
+
+    #[test]
+    fn frames_capture_the_enclosing_withs() {
+        let root = rnix::Root::parse("with outer; x: with inner; y: with own; y").tree();
+        let lambda = root
+            .syntax()
+            .descendants()
+            .filter_map(ast::Lambda::cast)
+            .nth(1)
+            .unwrap();
+
+        // outermost first, the lambda's own `with` is not captured
+        assert_eq!(captured_withs(lambda.syntax()), vec!["outer", "inner"]);
+    }
+
+    #[test]
+    fn free_paths_skip_what_the_expression_binds() {
+        let paths = free_paths("let b = a.x.${y}; in [ b.z c.d (x: x) ({ e ? f }: e) ]");
+        assert_eq!(
+            paths,
+            vec![vec!["a", "x"], vec!["y"], vec!["c", "d"], vec!["f"]]
+        );
+        assert_eq!(free_paths("let inherit a; in a"), vec![vec!["a"]]);
+    }
+
+    #[test]
+    fn attribute_paths_are_plain_selections() {
+        assert!(is_attribute_path("pkgs.hello.name"));
+        assert!(is_attribute_path(" x "));
+        assert!(!is_attribute_path("pkgs.hello or null"));
+        assert!(!is_attribute_path("pkgs.${name}"));
+        assert!(!is_attribute_path("f x"));
+        assert!(!is_attribute_path("a."));
+    }
+}
@@ -0,0 +1,128 @@
+use serde::{Deserialize, Serialize};
+use strum::Display;
+
+/// What runs in a frame of the VM
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Display)]
+#[strum(serialize_all = "lowercase")]
+pub enum FrameKind {
+    /// the code of a file, the evaluated one or an import
+    Toplevel,
+    Lambda,
+    Thunk,
+    /// a builtin being called, its work happens in generators
+    Builtin,
+    /// native code of tvix, e.g. the body of a builtin
+    Generator,
+}
+
+impl FrameKind {
+    /// Frames running nix code, as opposed to the native ones tvix runs
+    pub fn is_code(&self) -> bool {
+        matches!(
+            self,
+            FrameKind::Toplevel | FrameKind::Lambda | FrameKind::Thunk
+        )
+    }
+}
+
+/// A frame of the paused program as shown to the user, lines and columns
+/// start at 1. Native frames have no position
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StackFrame {
+    /// position of the frame from the bottom of the stack, stays the same
+    /// while the program is paused
+    pub id: usize,
+    pub name: String,
+    pub kind: FrameKind,
+    pub file: Option<String>,
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+    pub end_line: Option<usize>,
+    pub end_column: Option<usize>,
+}
+
+impl std::fmt::Display for StackFrame {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "#{} {}", self.id, self.name)?;
+        match (&self.file, self.line, self.column) {
+            (Some(file), Some(line), Some(column)) => {
+                write!(f, " at {}:{}:{}", file, line, column)
+            }
+            _ => write!(f, " ({})", self.kind),
+        }
+    }
+}
+
+/// Where the variables of a scope come from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Display)]
+#[strum(serialize_all = "lowercase")]
+pub enum ScopeKind {
+    /// arguments and `let` bindings of the frame
+    Locals,
+    /// variables the closure captured
+    Upvalues,
+    /// the attribute set of a `with`
+    With,
+    Builtins,
+}
+
+/// A scope of a paused frame, its variables are listed by a variables
+/// request for its reference
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Scope {
+    pub name: String,
+    pub kind: ScopeKind,
+    pub variables_reference: usize,
+    pub named_variables: usize,
+}
+
+impl std::fmt::Display for Scope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} ({} variables, reference {})",
+            self.name, self.named_variables, self.variables_reference
+        )
+    }
+}
+
+/// The `levels` frames starting `start` frames below the top of `frames`,
+/// which are ordered from the top. No levels means all the remaining ones
+pub fn page(frames: Vec<StackFrame>, start: usize, levels: Option<usize>) -> Vec<StackFrame> {
+    let frames = frames.into_iter().skip(start);
+    match levels {
+        Some(levels) if levels > 0 => frames.take(levels).collect(),
+        _ => frames.collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(id: usize) -> StackFrame {
+        StackFrame {
+            id,
+            name: format!("f{}", id),
+            kind: FrameKind::Lambda,
+            file: None,
+            line: None,
+            column: None,
+            end_line: None,
+            end_column: None,
+        }
+    }
+
+    #[test]
+    fn pages_from_the_top() {
+        let frames: Vec<_> = (0..5).rev().map(frame).collect();
+        let ids = |frames: Vec<StackFrame>| frames.iter().map(|f| f.id).collect::<Vec<_>>();
+
+        assert_eq!(ids(page(frames.clone(), 0, Some(2))), vec![4, 3]);
+        assert_eq!(ids(page(frames.clone(), 2, Some(2))), vec![2, 1]);
+        assert_eq!(ids(page(frames.clone(), 3, None)), vec![1, 0]);
+        // levels of 0 means all of them, as in DAP
+        assert_eq!(ids(page(frames.clone(), 4, Some(0))), vec![0]);
+        assert!(page(frames, 7, Some(3)).is_empty());
+    }
+}
@@ -0,0 +1,139 @@
+use serde::{Deserialize, Serialize};
+use smol_str::SmolStr;
+use tvix_eval::Value;
+
+/// What a `variablesReference` stands for, only valid while the program
+/// stays paused
+#[derive(Debug, Clone)]
+pub enum Container {
+    /// named values, e.g. the locals of a frame
+    Bindings(Vec<(SmolStr, Value)>),
+    /// the attributes of a set or the elements of a list
+    Value(Value),
+    /// an unforced thunk, listing it forces it
+    Force { name: String, thunk: Value },
+}
+
+/// The containers handed out since the program paused. References keep
+/// counting across pauses, so that the ones of an earlier pause are not
+/// mistaken for new ones. 0 stands for nothing to expand
+#[derive(Debug, Default)]
+pub struct Registry {
+    /// reference of the first container, minus one
+    offset: usize,
+    containers: Vec<Container>,
+}
+
+impl Registry {
+    pub fn insert(&mut self, container: Container) -> usize {
+        self.containers.push(container);
+        self.offset + self.containers.len()
+    }
+
+    pub fn get(&self, reference: usize) -> Option<&Container> {
+        reference
+            .checked_sub(self.offset + 1)
+            .and_then(|index| self.containers.get(index))
+    }
+
+    /// Forget every container, the program is about to run again
+    pub fn clear(&mut self) {
+        self.offset += self.containers.len();
+        self.containers.clear();
+    }
+}
+
+/// A variable as shown to the user, with the reference listing its
+/// children if it has any
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Variable {
+    pub name: String,
+    pub value: String,
+    pub type_name: Option<String>,
+    pub variables_reference: usize,
+    pub named_variables: Option<usize>,
+    pub indexed_variables: Option<usize>,
+    /// an unforced thunk, the user decides whether to force it
+    pub lazy: bool,
+    /// the user replaced the value before the program forced it
+    pub overridden: bool,
+}
+
+impl std::fmt::Display for Variable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} = {}", self.name, self.value)?;
+        if self.overridden {
+            write!(f, " «overridden»")?;
+        }
+        if self.variables_reference > 0 {
+            write!(f, " (vars {})", self.variables_reference)?;
+        }
+        Ok(())
+    }
+}
+
+/// The value of an already forced thunk, any other value as is
+pub fn evaluated(value: &Value) -> Value {
+    match value {
+        Value::Thunk(thunk) if thunk.is_evaluated() => evaluated(&thunk.value()),
+        other => other.clone(),
+    }
+}
+
+/// Whether `value` is a thunk nobody forced yet
+pub fn is_unforced(value: &Value) -> bool {
+    matches!(value, Value::Thunk(thunk) if !thunk.is_evaluated())
+}
+
+/// The attributes of a set, also of an already forced thunk of a set.
+/// Unforced thunks stay untouched
+pub fn attrs_bindings(value: &Value) -> Option<Vec<(SmolStr, Value)>> {
+    match evaluated(value) {
+        Value::Attrs(attrs) => Some(
+            attrs
+                .iter()
+                .map(|(name, value)| {
+                    let name = String::from_utf8_lossy(name.as_bytes());
+                    (SmolStr::from(name.as_ref()), value.clone())
+                })
+                .collect(),
+        ),
+        _ => None,
+    }
+}
+
+/// The children of a set or list, by name and index respectively
+pub fn children(value: &Value) -> Vec<(String, Value)> {
+    match evaluated(value) {
+        Value::List(list) => list
+            .iter()
+            .enumerate()
+            .map(|(index, value)| (format!("[{}]", index), value.clone()))
+            .collect(),
+        value => attrs_bindings(&value)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), value))
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn references_of_an_earlier_pause_are_invalid() {
+        let mut registry = Registry::default();
+        let first = registry.insert(Container::Bindings(vec![]));
+        assert_eq!(first, 1);
+        assert!(registry.get(0).is_none());
+        assert!(registry.get(first).is_some());
+
+        registry.clear();
+        assert!(registry.get(first).is_none());
+        let second = registry.insert(Container::Value(Value::Null));
+        assert_eq!(second, 2);
+        assert!(matches!(registry.get(second), Some(Container::Value(_))));
+    }
+}
@@ -0,0 +1,5 @@
+let
+  a = 3 + 1;
+in
+assert a == 10;
+a
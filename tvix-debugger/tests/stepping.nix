@@ -0,0 +1,8 @@
+let
+  double = x:
+    x * 2;
+  a = double 1;
+  b = double 2;
+in
+a
++ b
@@ -0,0 +1,4 @@
+let
+  fail = msg: throw msg;
+in
+fail "boom"